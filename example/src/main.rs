@@ -39,6 +39,7 @@ pub struct MyStreamConfig {
     pub un_compress_size: u64,
     pub open_files: u16,
     pub source: Option<Arc<dyn ReadAt>>,
+    pub temp_dir: Option<PathBuf>,
 }
 impl Display for MyStreamConfig {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -79,7 +80,7 @@ impl Config for MyStreamConfig {
     }
 
     fn temp_dir(&self) -> Option<PathBuf> {
-        None
+        self.temp_dir.clone()
     }
 }
 impl BinWrite for MyStreamConfig {
@@ -127,10 +128,17 @@ impl BinRead for MyStreamConfig {
                 un_compress_size: reader.read_type(endian).await?,
                 open_files: reader.read_type(endian).await?,
                 source: None,
+                temp_dir: None,
             })
         }
     }
 }
+fn temp_file(config: &MyStreamConfig) -> std::io::Result<File> {
+    match &config.temp_dir {
+        Some(dir) => tempfile::tempfile_in(dir),
+        None => tempfile::tempfile(),
+    }
+}
 impl StreamDefault for MyData {
     type Config = MyStreamConfig;
 
@@ -154,7 +162,7 @@ impl StreamDefault for MyData {
             config.source = None;
             if let (size, Some(limit_size)) = (config.compress_size, config.limit_size) {
                 if size > limit_size {
-                    let tempfile = tempfile::tempfile()?;
+                    let tempfile = temp_file(&config)?;
                     return Ok(Self::File {
                         inner: tempfile.into(),
                         config,
@@ -163,7 +171,7 @@ impl StreamDefault for MyData {
             }
             if let (size, Some(limit_size)) = (config.un_compress_size, config.limit_size) {
                 if size > limit_size {
-                    let tempfile = tempfile::tempfile()?;
+                    let tempfile = temp_file(&config)?;
                     return Ok(Self::File {
                         inner: tempfile.into(),
                         config,
@@ -201,6 +209,20 @@ impl StreamDefault for MyData {
         }
     }
 
+    fn from_temp(
+        temp_dir: &std::path::Path,
+        config: &Self::Config,
+    ) -> impl Future<Output = BinResult<Option<Self>>> + Send {
+        async move {
+            let mut config = config.clone();
+            config.source = None;
+            Ok(Some(Self::File {
+                inner: tempfile::tempfile_in(temp_dir)?,
+                config,
+            }))
+        }
+    }
+
     fn from(&self) -> impl Future<Output = BinResult<Self>> + Send {
         MyData::from_config(self.config())
     }
@@ -0,0 +1,101 @@
+use binrw::BinResult;
+use binrw::io::read::Read;
+use binrw::io::seek::Seek;
+use binrw::io::write::Write;
+use rzip::zip::{Config, StreamDefault};
+use std::io::{Cursor, SeekFrom};
+use std::path::PathBuf;
+
+// 只在内存中保存数据的最小实现
+#[derive(Default, Clone)]
+pub struct MemConfig {
+    compress_size: u64,
+    un_compress_size: u64,
+}
+impl Config for MemConfig {
+    fn compress_size(&self) -> u64 {
+        self.compress_size
+    }
+
+    fn un_compress_size(&self) -> u64 {
+        self.un_compress_size
+    }
+
+    fn compress_size_mut(&mut self, value: u64) {
+        self.compress_size = value;
+    }
+
+    fn un_compress_size_mut(&mut self, value: u64) {
+        self.un_compress_size = value;
+    }
+
+    fn temp_dir(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+#[derive(Default)]
+pub struct MemData {
+    inner: Cursor<Vec<u8>>,
+    config: MemConfig,
+}
+impl MemData {
+    pub fn new(data: &[u8]) -> Self {
+        Self {
+            inner: Cursor::new(data.to_vec()),
+            config: MemConfig::default(),
+        }
+    }
+}
+impl StreamDefault for MemData {
+    type Config = MemConfig;
+
+    fn from(&self) -> impl Future<Output = BinResult<Self>> + Send {
+        MemData::from_config(self.config())
+    }
+
+    fn from_config(config: &Self::Config) -> impl Future<Output = BinResult<Self>> + Send {
+        async move {
+            Ok(Self {
+                inner: Cursor::new(vec![]),
+                config: config.clone(),
+            })
+        }
+    }
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn link(&self) -> impl Future<Output = BinResult<Self>> + Send {
+        async move {
+            Ok(Self {
+                inner: Cursor::new(self.inner.get_ref().clone()),
+                config: self.config.clone(),
+            })
+        }
+    }
+}
+impl Read for MemData {
+    async fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        std::io::Read::read(&mut self.inner, buf)
+    }
+
+    async fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+impl Write for MemData {
+    async fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        std::io::Write::write(&mut self.inner, buf)
+    }
+
+    async fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+impl Seek for MemData {
+    async fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        std::io::Seek::seek(&mut self.inner, pos)
+    }
+}
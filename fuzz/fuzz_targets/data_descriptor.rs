@@ -0,0 +1,16 @@
+#![no_main]
+
+use binrw::BinReaderExt;
+use libfuzzer_sys::fuzz_target;
+use rzip::file::DataDescriptor;
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&zip64, data)) = data.split_first() else {
+        return;
+    };
+    let mut reader = Cursor::new(data.to_vec());
+    futures::executor::block_on(async {
+        let _ = reader.read_le_args::<DataDescriptor>(zip64 & 1 != 0).await;
+    });
+});
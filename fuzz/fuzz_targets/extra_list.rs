@@ -0,0 +1,25 @@
+#![no_main]
+
+use binrw::BinReaderExt;
+use libfuzzer_sys::fuzz_target;
+use rzip::file::ExtraList;
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let length = data.len().min(u16::MAX as usize) as u16;
+    let mut reader = Cursor::new(data.to_vec());
+    futures::executor::block_on(async {
+        if let Ok(extra_fields) = reader.read_le_args::<ExtraList>(length).await {
+            let (mut uncompressed_size, mut compressed_size, mut offset) =
+                (0xFFFFFFFF, 0xFFFFFFFF, 0xFFFFFFFF);
+            let _ = extra_fields.resolve_zip64(
+                &mut uncompressed_size,
+                &mut compressed_size,
+                Some(&mut offset),
+            );
+            let _ = extra_fields.unicode_path(b"name");
+            let _ = extra_fields.unicode_comment(b"comment");
+            let _ = extra_fields.bytes().await;
+        }
+    });
+});
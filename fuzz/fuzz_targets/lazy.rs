@@ -0,0 +1,41 @@
+#![no_main]
+
+mod common;
+
+use binrw::io::read::Read;
+use common::MemData;
+use libfuzzer_sys::fuzz_target;
+use rzip::limits::Limits;
+use rzip::options::ParseOptions;
+use rzip::zip::FastZip;
+
+// 只读中央目录后逐个打开条目并读到末尾，覆盖按需读取本地头、解密和流式解压。
+// 压缩算法和AES随features启用，cargo fuzz run lazy --features all
+fuzz_target!(|data: &[u8]| {
+    let mut options = ParseOptions::new();
+    options.set_limits(Limits {
+        max_total_size: Some(64 * 1024 * 1024),
+        max_entry_size: Some(16 * 1024 * 1024),
+        max_ratio: Some(1024),
+        max_entries: Some(4096),
+        max_name_length: Some(4096),
+    });
+    futures::executor::block_on(async {
+        let Ok(mut zip) = FastZip::parse_lazy(MemData::new(data), &options).await else {
+            return;
+        };
+        zip.password = Some(b"password".to_vec());
+        let names: Vec<String> = zip.directories.0.keys().cloned().collect();
+        let mut buffer = vec![0u8; 16 * 1024];
+        for name in names {
+            let Ok(Some(mut reader)) = zip.open_entry(&name).await else {
+                continue;
+            };
+            while let Ok(len) = reader.read(&mut buffer).await {
+                if len == 0 {
+                    break;
+                }
+            }
+        }
+    });
+});
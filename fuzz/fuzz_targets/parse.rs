@@ -0,0 +1,31 @@
+#![no_main]
+
+mod common;
+
+use common::MemData;
+use libfuzzer_sys::fuzz_target;
+use rzip::limits::Limits;
+use rzip::options::ParseOptions;
+use rzip::zip::FastZip;
+
+fuzz_target!(|data: &[u8]| {
+    let mut options = ParseOptions::new();
+    // 限制输出，避免构造的压缩炸弹拖慢模糊测试
+    options.set_limits(Limits {
+        max_total_size: Some(64 * 1024 * 1024),
+        max_entry_size: Some(16 * 1024 * 1024),
+        max_ratio: Some(1024),
+        max_entries: Some(4096),
+        max_name_length: Some(4096),
+    });
+    let mut reader = MemData::new(data);
+    futures::executor::block_on(async {
+        if let Ok(mut zip) = FastZip::parse_with_options(&mut reader, &options).await {
+            for (_, dir) in &zip.directories.0 {
+                let _ = dir.modified(Default::default());
+                let _ = dir.unix_mode();
+            }
+            let _ = zip.verify().await;
+        }
+    });
+});
@@ -0,0 +1,699 @@
+use crate::deflate64::Deflate64Decoder;
+use crate::directory::CompressionMethod;
+use crate::error::ZipError;
+use binrw::io::read::Read;
+use binrw::io::seek::Seek;
+use binrw::io::write::Write;
+use binrw::{BinResult, Error};
+use miniz_oxide::deflate::CompressionLevel;
+use miniz_oxide::inflate::stream::{InflateState, inflate};
+use miniz_oxide::{DataFormat, MZError, MZFlush, MZStatus};
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// 同步的增量解码器，由异步读写循环驱动
+pub(crate) trait Decoder: Send {
+    // 返回 (消耗的输入, 产生的输出, 是否结束)
+    fn decode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        eof: bool,
+    ) -> std::io::Result<(usize, usize, bool)>;
+}
+pub(crate) trait Encoder: Send {
+    // finish为true表示input之后没有更多数据
+    fn encode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        finish: bool,
+    ) -> std::io::Result<(usize, usize, bool)>;
+}
+
+/// 当前启用的特性下是否可以压缩/解压该算法
+pub fn is_supported(method: &CompressionMethod) -> bool {
+    match method {
+        CompressionMethod::Store | CompressionMethod::Deflate | CompressionMethod::Deflate64 => {
+            true
+        }
+        #[cfg(feature = "use_zstd")]
+        CompressionMethod::Zstd => true,
+        #[cfg(feature = "use_bzip2")]
+        CompressionMethod::BZIP2 => true,
+        #[cfg(feature = "use_lzma")]
+        CompressionMethod::LZMA | CompressionMethod::XZ => true,
+        _ => false,
+    }
+}
+
+/// 打包时该算法需要额外设置的通用标志位
+pub fn general_flags(method: &CompressionMethod) -> u16 {
+    match method {
+        //LZMA使用结束标记(EOS)
+        CompressionMethod::LZMA => 0x02,
+        _ => 0,
+    }
+}
+
+/// zstd没有不压缩的级别，按照速度/压缩率映射
+pub fn zstd_level(compression_level: CompressionLevel) -> i32 {
+    match compression_level {
+        CompressionLevel::NoCompression => -7,
+        CompressionLevel::BestSpeed => 1,
+        CompressionLevel::DefaultLevel | CompressionLevel::DefaultCompression => 3,
+        CompressionLevel::BestCompression => 19,
+        CompressionLevel::UberCompression => 22,
+    }
+}
+
+/// bzip2的块大小 1-9 (x100k)
+pub fn bzip2_level(compression_level: CompressionLevel) -> u32 {
+    match compression_level {
+        CompressionLevel::NoCompression | CompressionLevel::BestSpeed => 1,
+        CompressionLevel::DefaultLevel | CompressionLevel::DefaultCompression => 6,
+        CompressionLevel::BestCompression | CompressionLevel::UberCompression => 9,
+    }
+}
+
+/// LZMA/XZ的预设等级，Uber使用extreme模式
+pub fn xz_preset(compression_level: CompressionLevel) -> u32 {
+    match compression_level {
+        CompressionLevel::NoCompression => 0,
+        CompressionLevel::BestSpeed => 1,
+        CompressionLevel::DefaultLevel | CompressionLevel::DefaultCompression => 6,
+        CompressionLevel::BestCompression => 9,
+        CompressionLevel::UberCompression => 9 | 0x8000_0000,
+    }
+}
+
+pub(crate) fn unsupported(method: &CompressionMethod) -> Error {
+    ZipError::UnsupportedMethod {
+        name: None,
+        offset: None,
+        method: method.clone().into(),
+    }
+    .into()
+}
+
+// 编码器创建或压缩失败，读写数据的错误仍为Io
+fn compress_error(error: impl std::fmt::Display) -> Error {
+    ZipError::corrupt(format!("compress failed: {}", error)).into()
+}
+
+// flags和uncompressed_size用于LZMA判断是否有结束标记
+pub(crate) fn decompress<R, W>(
+    method: &CompressionMethod,
+    flags: u16,
+    uncompressed_size: u64,
+    reader: &mut R,
+    writer: &mut W,
+) -> impl Future<Output = BinResult<()>> + Send
+where
+    R: Read + Seek + Send,
+    W: Write + Seek + Send,
+{
+    async move {
+        match method {
+            CompressionMethod::Store => {
+                binrw::io::copy(reader, writer).await?;
+                Ok(())
+            }
+            CompressionMethod::Deflate => {
+                miniz_oxide::inflate::stream::decompress_stream(reader, writer)
+                    .await
+                    .map_err(|e| ZipError::corrupt(e.to_string()))?;
+                Ok(())
+            }
+            _ => drive_decoder(decoder(method, flags, uncompressed_size)?, reader, writer).await,
+        }
+    }
+}
+
+// zstd不为None时Zstd使用该级别，不按compression_level换算
+pub(crate) fn compress<R, W>(
+    method: &CompressionMethod,
+    compression_level: CompressionLevel,
+    zstd: Option<i32>,
+    reader: &mut R,
+    writer: &mut W,
+) -> impl Future<Output = BinResult<()>> + Send
+where
+    R: Read + Seek + Send,
+    W: Write + Seek + Send,
+{
+    async move {
+        match method {
+            CompressionMethod::Store => {
+                binrw::io::copy(reader, writer).await?;
+                Ok(())
+            }
+            CompressionMethod::Deflate => {
+                miniz_oxide::deflate::stream::compress_stream_callback(
+                    reader,
+                    writer,
+                    compression_level,
+                )
+                .await
+                .map_err(compress_error)?;
+                Ok(())
+            }
+            _ => drive_encoder(encoder(method, compression_level, zstd)?, reader, writer).await,
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "use_lzma"), allow(unused_variables))]
+fn decoder(
+    method: &CompressionMethod,
+    flags: u16,
+    uncompressed_size: u64,
+) -> BinResult<Box<dyn Decoder>> {
+    match method {
+        CompressionMethod::Deflate64 => Ok(Box::new(Deflate64Decoder::new())),
+        #[cfg(feature = "use_zstd")]
+        CompressionMethod::Zstd => Ok(Box::new(zstd_codec::ZstdDecoder::new()?)),
+        #[cfg(feature = "use_bzip2")]
+        CompressionMethod::BZIP2 => Ok(Box::new(bzip2_codec::Bzip2Decoder::new())),
+        #[cfg(feature = "use_lzma")]
+        CompressionMethod::LZMA => {
+            let size = if flags & 0x02 != 0 {
+                None
+            } else {
+                Some(uncompressed_size)
+            };
+            Ok(Box::new(lzma_codec::LzmaDecoder::new(size)))
+        }
+        #[cfg(feature = "use_lzma")]
+        CompressionMethod::XZ => Ok(Box::new(lzma_codec::XzDecoder::new()?)),
+        _ => Err(unsupported(method)),
+    }
+}
+
+/// 按需读取时使用的解码器，Store不需要解码返回None
+pub(crate) fn stream_decoder(
+    method: &CompressionMethod,
+    flags: u16,
+    uncompressed_size: u64,
+) -> BinResult<Option<Box<dyn Decoder>>> {
+    match method {
+        CompressionMethod::Store => Ok(None),
+        CompressionMethod::Deflate => Ok(Some(Box::new(DeflateDecoder::new()))),
+        _ => decoder(method, flags, uncompressed_size).map(Some),
+    }
+}
+
+// 整体解压使用decompress_stream，按需读取时按块解压
+struct DeflateDecoder {
+    state: Box<InflateState>,
+}
+impl DeflateDecoder {
+    fn new() -> Self {
+        Self {
+            state: InflateState::new_boxed(DataFormat::Raw),
+        }
+    }
+}
+impl Decoder for DeflateDecoder {
+    fn decode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        _eof: bool,
+    ) -> std::io::Result<(usize, usize, bool)> {
+        let result = inflate(&mut self.state, input, output, MZFlush::None);
+        let (consumed, produced) = (result.bytes_consumed, result.bytes_written);
+        match result.status {
+            Ok(MZStatus::StreamEnd) => Ok((consumed, produced, true)),
+            //Buf表示没有进展，需要更多输入
+            Ok(_) | Err(MZError::Buf) => Ok((consumed, produced, false)),
+            Err(e) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("inflate failed: {:?}", e),
+            )),
+        }
+    }
+}
+
+#[cfg_attr(
+    not(any(feature = "use_zstd", feature = "use_bzip2", feature = "use_lzma")),
+    allow(unused_variables)
+)]
+fn encoder(
+    method: &CompressionMethod,
+    compression_level: CompressionLevel,
+    zstd: Option<i32>,
+) -> BinResult<Box<dyn Encoder>> {
+    match method {
+        #[cfg(feature = "use_zstd")]
+        CompressionMethod::Zstd => Ok(Box::new(
+            zstd_codec::ZstdEncoder::new(zstd.unwrap_or_else(|| zstd_level(compression_level)))
+                .map_err(compress_error)?,
+        )),
+        #[cfg(feature = "use_bzip2")]
+        CompressionMethod::BZIP2 => Ok(Box::new(bzip2_codec::Bzip2Encoder::new(bzip2_level(
+            compression_level,
+        )))),
+        #[cfg(feature = "use_lzma")]
+        CompressionMethod::LZMA => Ok(Box::new(
+            lzma_codec::LzmaEncoder::new(xz_preset(compression_level)).map_err(compress_error)?,
+        )),
+        #[cfg(feature = "use_lzma")]
+        CompressionMethod::XZ => Ok(Box::new(
+            lzma_codec::XzEncoder::new(xz_preset(compression_level)).map_err(compress_error)?,
+        )),
+        _ => Err(unsupported(method)),
+    }
+}
+
+fn drive_decoder<R, W>(
+    decoder: Box<dyn Decoder>,
+    reader: &mut R,
+    writer: &mut W,
+) -> impl Future<Output = BinResult<()>> + Send
+where
+    R: Read + Send,
+    W: Write + Send,
+{
+    async move {
+        let mut decoder = PullDecoder::new(decoder);
+        let mut output = vec![0u8; BUFFER_SIZE];
+        loop {
+            let produced = decoder.decode(reader, &mut output).await?;
+            if produced == 0 {
+                return Ok(());
+            }
+            writer.write_all(&output[..produced]).await?;
+        }
+    }
+}
+
+/// 从reader按需读取压缩数据，每次调用输出一块解压数据，整体解压和EntryReader共用
+pub(crate) struct PullDecoder {
+    decoder: Box<dyn Decoder>,
+    input: Vec<u8>,
+    start: usize,
+    end: usize,
+    eof: bool,
+    // 解码器已输出全部数据
+    done: bool,
+}
+impl PullDecoder {
+    pub(crate) fn new(decoder: Box<dyn Decoder>) -> Self {
+        Self {
+            decoder,
+            input: vec![],
+            start: 0,
+            end: 0,
+            eof: false,
+            done: false,
+        }
+    }
+    /// 解压到output，返回输出的字节数，0表示数据已结束
+    pub(crate) fn decode<'a, R>(
+        &'a mut self,
+        reader: &'a mut R,
+        output: &'a mut [u8],
+    ) -> impl Future<Output = std::io::Result<usize>> + Send + 'a
+    where
+        R: Read + Send,
+    {
+        async move {
+            loop {
+                if self.done {
+                    return Ok(0);
+                }
+                if self.start == self.end && !self.eof {
+                    let first = self.input.is_empty();
+                    if first {
+                        self.input.resize(BUFFER_SIZE, 0);
+                    }
+                    self.start = 0;
+                    self.end = reader.read(&mut self.input).await?;
+                    self.eof = self.end == 0;
+                    //空文件不压缩，没有任何数据
+                    if first && self.eof {
+                        self.done = true;
+                        return Ok(0);
+                    }
+                }
+                let (consumed, produced, done) =
+                    self.decoder
+                        .decode(&self.input[self.start..self.end], output, self.eof)?;
+                self.start += consumed;
+                self.done = done;
+                if produced > 0 || done {
+                    return Ok(produced);
+                }
+                if consumed == 0 {
+                    if self.eof {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "unexpected end of compressed data",
+                        ));
+                    }
+                    //解码器需要更多输入
+                    self.input.copy_within(self.start..self.end, 0);
+                    self.end -= self.start;
+                    self.start = 0;
+                    if self.end == self.input.len() {
+                        self.input.resize(self.input.len() * 2, 0);
+                    }
+                    let len = reader.read(&mut self.input[self.end..]).await?;
+                    self.eof = len == 0;
+                    self.end += len;
+                }
+            }
+        }
+    }
+}
+
+fn drive_encoder<R, W>(
+    mut encoder: Box<dyn Encoder>,
+    reader: &mut R,
+    writer: &mut W,
+) -> impl Future<Output = BinResult<()>> + Send
+where
+    R: Read + Send,
+    W: Write + Send,
+{
+    async move {
+        let mut input = vec![0u8; BUFFER_SIZE];
+        let mut output = vec![0u8; BUFFER_SIZE];
+        let (mut start, mut end, mut eof) = (0, 0, false);
+        loop {
+            if start == end && !eof {
+                start = 0;
+                end = reader.read(&mut input).await?;
+                eof = end == 0;
+            }
+            let (consumed, produced, done) = encoder
+                .encode(&input[start..end], &mut output, eof)
+                .map_err(compress_error)?;
+            start += consumed;
+            if produced > 0 {
+                writer.write_all(&output[..produced]).await?;
+            }
+            if done {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(feature = "use_zstd")]
+mod zstd_codec {
+    use super::{Decoder, Encoder};
+    use zstd::stream::raw::{InBuffer, Operation, OutBuffer};
+
+    pub struct ZstdDecoder {
+        inner: zstd::stream::raw::Decoder<'static>,
+        frame_done: bool,
+    }
+    impl ZstdDecoder {
+        pub fn new() -> std::io::Result<Self> {
+            Ok(Self {
+                inner: zstd::stream::raw::Decoder::new()?,
+                frame_done: false,
+            })
+        }
+    }
+    impl Decoder for ZstdDecoder {
+        fn decode(
+            &mut self,
+            input: &[u8],
+            output: &mut [u8],
+            eof: bool,
+        ) -> std::io::Result<(usize, usize, bool)> {
+            if eof && input.is_empty() && self.frame_done {
+                return Ok((0, 0, true));
+            }
+            let mut in_buffer = InBuffer::around(input);
+            let mut out_buffer = OutBuffer::around(output);
+            let hint = self.inner.run(&mut in_buffer, &mut out_buffer)?;
+            //hint为0表示一帧已完整解码并输出，后面可能还有帧
+            self.frame_done = hint == 0;
+            Ok((in_buffer.pos, out_buffer.pos(), false))
+        }
+    }
+
+    pub struct ZstdEncoder {
+        inner: zstd::stream::raw::Encoder<'static>,
+    }
+    impl ZstdEncoder {
+        pub fn new(level: i32) -> std::io::Result<Self> {
+            Ok(Self {
+                inner: zstd::stream::raw::Encoder::new(level)?,
+            })
+        }
+    }
+    impl Encoder for ZstdEncoder {
+        fn encode(
+            &mut self,
+            input: &[u8],
+            output: &mut [u8],
+            finish: bool,
+        ) -> std::io::Result<(usize, usize, bool)> {
+            let mut in_buffer = InBuffer::around(input);
+            let mut out_buffer = OutBuffer::around(output);
+            if !input.is_empty() {
+                self.inner.run(&mut in_buffer, &mut out_buffer)?;
+            }
+            if !finish || in_buffer.pos < input.len() {
+                return Ok((in_buffer.pos, out_buffer.pos(), false));
+            }
+            let remaining = self.inner.finish(&mut out_buffer, true)?;
+            Ok((in_buffer.pos, out_buffer.pos(), remaining == 0))
+        }
+    }
+}
+
+#[cfg(feature = "use_bzip2")]
+mod bzip2_codec {
+    use super::{Decoder, Encoder};
+    use bzip2::{Action, Compress, Compression, Decompress, Status};
+
+    pub struct Bzip2Decoder {
+        inner: Decompress,
+    }
+    impl Bzip2Decoder {
+        pub fn new() -> Self {
+            Self {
+                inner: Decompress::new(false),
+            }
+        }
+    }
+    impl Decoder for Bzip2Decoder {
+        fn decode(
+            &mut self,
+            input: &[u8],
+            output: &mut [u8],
+            _eof: bool,
+        ) -> std::io::Result<(usize, usize, bool)> {
+            let (total_in, total_out) = (self.inner.total_in(), self.inner.total_out());
+            let status = self.inner.decompress(input, output)?;
+            Ok((
+                (self.inner.total_in() - total_in) as usize,
+                (self.inner.total_out() - total_out) as usize,
+                status == Status::StreamEnd,
+            ))
+        }
+    }
+
+    pub struct Bzip2Encoder {
+        inner: Compress,
+    }
+    impl Bzip2Encoder {
+        pub fn new(level: u32) -> Self {
+            Self {
+                inner: Compress::new(Compression::new(level), 30),
+            }
+        }
+    }
+    impl Encoder for Bzip2Encoder {
+        fn encode(
+            &mut self,
+            input: &[u8],
+            output: &mut [u8],
+            finish: bool,
+        ) -> std::io::Result<(usize, usize, bool)> {
+            let action = if finish { Action::Finish } else { Action::Run };
+            let (total_in, total_out) = (self.inner.total_in(), self.inner.total_out());
+            let status = self.inner.compress(input, output, action)?;
+            Ok((
+                (self.inner.total_in() - total_in) as usize,
+                (self.inner.total_out() - total_out) as usize,
+                status == Status::StreamEnd,
+            ))
+        }
+    }
+}
+
+#[cfg(feature = "use_lzma")]
+mod lzma_codec {
+    use super::{Decoder, Encoder};
+    use xz2::stream::{Action, Check, LzmaOptions, Status, Stream};
+
+    // zip中LZMA数据头: 版本(2) + 属性长度(2) + 属性(5)
+    const ZIP_HEADER_SIZE: usize = 9;
+    // lzma_alone数据头: 属性(5) + 解压大小(8)
+    const ALONE_HEADER_SIZE: usize = 13;
+
+    fn process(
+        stream: &mut Stream,
+        input: &[u8],
+        output: &mut [u8],
+        action: Action,
+    ) -> std::io::Result<(usize, usize, bool)> {
+        let (total_in, total_out) = (stream.total_in(), stream.total_out());
+        let status = stream.process(input, output, action)?;
+        Ok((
+            (stream.total_in() - total_in) as usize,
+            (stream.total_out() - total_out) as usize,
+            status == Status::StreamEnd,
+        ))
+    }
+
+    pub struct LzmaDecoder {
+        inner: Option<Stream>,
+        size: Option<u64>,
+        header: Vec<u8>,
+    }
+    impl LzmaDecoder {
+        // size为None表示数据以结束标记结尾
+        pub fn new(size: Option<u64>) -> Self {
+            Self {
+                inner: None,
+                size,
+                header: Vec::with_capacity(ALONE_HEADER_SIZE),
+            }
+        }
+    }
+    impl Decoder for LzmaDecoder {
+        fn decode(
+            &mut self,
+            input: &[u8],
+            output: &mut [u8],
+            _eof: bool,
+        ) -> std::io::Result<(usize, usize, bool)> {
+            let Some(inner) = &mut self.inner else {
+                let take = input.len().min(ZIP_HEADER_SIZE - self.header.len());
+                self.header.extend_from_slice(&input[..take]);
+                if self.header.len() < ZIP_HEADER_SIZE {
+                    return Ok((take, 0, false));
+                }
+                let props_size = u16::from_le_bytes([self.header[2], self.header[3]]);
+                if props_size != 5 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("invalid lzma properties size {}", props_size),
+                    ));
+                }
+                //转换为lzma_alone头交给liblzma
+                self.header.drain(..4);
+                let size = self.size.unwrap_or(u64::MAX);
+                self.header.extend_from_slice(&size.to_le_bytes());
+                let mut stream = Stream::new_lzma_decoder(u64::MAX)?;
+                let (consumed, _, _) = process(&mut stream, &self.header, &mut [], Action::Run)?;
+                if consumed != ALONE_HEADER_SIZE {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "invalid lzma header",
+                    ));
+                }
+                self.inner = Some(stream);
+                return Ok((take, 0, false));
+            };
+            process(inner, input, output, Action::Run)
+        }
+    }
+
+    pub struct LzmaEncoder {
+        inner: Stream,
+        header: Vec<u8>,
+        pending: Vec<u8>,
+    }
+    impl LzmaEncoder {
+        pub fn new(preset: u32) -> std::io::Result<Self> {
+            let options = LzmaOptions::new_preset(preset)?;
+            Ok(Self {
+                inner: Stream::new_lzma_encoder(&options)?,
+                header: Vec::with_capacity(ALONE_HEADER_SIZE),
+                pending: vec![],
+            })
+        }
+    }
+    impl Encoder for LzmaEncoder {
+        fn encode(
+            &mut self,
+            input: &[u8],
+            output: &mut [u8],
+            finish: bool,
+        ) -> std::io::Result<(usize, usize, bool)> {
+            if !self.pending.is_empty() {
+                let len = self.pending.len().min(output.len());
+                output[..len].copy_from_slice(&self.pending[..len]);
+                self.pending.drain(..len);
+                return Ok((0, len, false));
+            }
+            let action = if finish { Action::Finish } else { Action::Run };
+            if self.header.len() < ALONE_HEADER_SIZE {
+                //lzma_alone头替换为zip的LZMA头，丢弃解压大小
+                let mut buffer = [0u8; ALONE_HEADER_SIZE];
+                let want = ALONE_HEADER_SIZE - self.header.len();
+                let (consumed, produced, _) =
+                    process(&mut self.inner, input, &mut buffer[..want], action)?;
+                self.header.extend_from_slice(&buffer[..produced]);
+                if self.header.len() == ALONE_HEADER_SIZE {
+                    self.pending = vec![9, 20, 5, 0];
+                    self.pending.extend_from_slice(&self.header[..5]);
+                }
+                return Ok((consumed, 0, false));
+            }
+            process(&mut self.inner, input, output, action)
+        }
+    }
+
+    pub struct XzDecoder {
+        inner: Stream,
+    }
+    impl XzDecoder {
+        pub fn new() -> std::io::Result<Self> {
+            Ok(Self {
+                inner: Stream::new_stream_decoder(u64::MAX, 0)?,
+            })
+        }
+    }
+    impl Decoder for XzDecoder {
+        fn decode(
+            &mut self,
+            input: &[u8],
+            output: &mut [u8],
+            _eof: bool,
+        ) -> std::io::Result<(usize, usize, bool)> {
+            process(&mut self.inner, input, output, Action::Run)
+        }
+    }
+
+    pub struct XzEncoder {
+        inner: Stream,
+    }
+    impl XzEncoder {
+        pub fn new(preset: u32) -> std::io::Result<Self> {
+            Ok(Self {
+                inner: Stream::new_easy_encoder(preset, Check::Crc64)?,
+            })
+        }
+    }
+    impl Encoder for XzEncoder {
+        fn encode(
+            &mut self,
+            input: &[u8],
+            output: &mut [u8],
+            finish: bool,
+        ) -> std::io::Result<(usize, usize, bool)> {
+            let action = if finish { Action::Finish } else { Action::Run };
+            process(&mut self.inner, input, output, action)
+        }
+    }
+}
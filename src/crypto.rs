@@ -0,0 +1,576 @@
+use crate::directory::CompressionMethod;
+use crate::extra::Extra;
+use binrw::io::read::Read;
+use binrw::io::seek::Seek;
+use binrw::io::write::Write;
+use binrw::{BinResult, Error};
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// WinZip AES扩展字段中的厂商标识 "AE"
+pub const AES_VENDOR_ID: u16 = 0x4541;
+/// AES加密所需的最低解压版本 5.1
+pub const AES_VERSION: u8 = 0x33;
+// HMAC-SHA1 截取前10字节作为认证码
+const AUTH_CODE_SIZE: usize = 10;
+const VERIFIER_SIZE: usize = 2;
+
+/// 根据条目名返回解压密码，返回None表示不解压该条目
+pub type PasswordProvider = Arc<dyn Fn(&str) -> Option<Vec<u8>> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AesStrength {
+    Aes128 = 1,
+    Aes192 = 2,
+    #[default]
+    Aes256 = 3,
+}
+impl AesStrength {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Self::Aes128),
+            2 => Some(Self::Aes192),
+            3 => Some(Self::Aes256),
+            _ => None,
+        }
+    }
+    pub fn key_size(&self) -> usize {
+        match self {
+            Self::Aes128 => 16,
+            Self::Aes192 => 24,
+            Self::Aes256 => 32,
+        }
+    }
+    pub fn salt_size(&self) -> usize {
+        self.key_size() / 2
+    }
+    /// 加密后数据比压缩数据多出的字节数
+    pub fn overhead(&self) -> u64 {
+        (self.salt_size() + VERIFIER_SIZE + AUTH_CODE_SIZE) as u64
+    }
+}
+
+/// 打包时使用的加密方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    Aes(AesStrength),
+    /// 传统PKWARE加密，安全性弱，仅用于兼容旧的解压工具
+    ZipCrypto,
+}
+impl Default for Encryption {
+    fn default() -> Self {
+        Self::Aes(AesStrength::default())
+    }
+}
+
+#[derive(Debug)]
+pub enum PasswordError {
+    /// 条目已加密但没有设置密码
+    Missing(String),
+    /// 密码校验值不匹配
+    Incorrect(String),
+    /// 认证码不匹配，数据被篡改或损坏
+    AuthenticationFailed(String),
+    /// 加密参数无法识别
+    Unsupported(String),
+}
+impl Display for PasswordError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(name) => write!(f, "{} is encrypted but no password was given", name),
+            Self::Incorrect(name) => write!(f, "incorrect password for {}", name),
+            Self::AuthenticationFailed(name) => {
+                write!(f, "authentication code mismatch for {}", name)
+            }
+            Self::Unsupported(name) => write!(f, "unsupported encryption for {}", name),
+        }
+    }
+}
+impl std::error::Error for PasswordError {}
+
+impl PasswordError {
+    pub(crate) fn into_error(self) -> Error {
+        Error::Err(Box::new(self))
+    }
+}
+
+/// 生成随机字节，用于盐值和传统加密头部，取自系统随机源
+#[cfg(feature = "use_aes")]
+pub(crate) fn random_bytes(len: usize) -> BinResult<Vec<u8>> {
+    let mut bytes = vec![0u8; len];
+    getrandom::fill(&mut bytes).map_err(|e| Error::Io(std::io::Error::other(e.to_string())))?;
+    Ok(bytes)
+}
+
+/// 未开启use_aes时只用于传统加密头部，该算法本身不提供保密性，不引入系统随机源
+#[cfg(not(feature = "use_aes"))]
+pub(crate) fn random_bytes(len: usize) -> BinResult<Vec<u8>> {
+    use std::hash::{BuildHasher, Hasher};
+    let mut bytes = Vec::with_capacity(len + 8);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    let mut counter = 0u64;
+    while bytes.len() < len {
+        //RandomState每次创建使用不同的随机种子
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        hasher.write_u64(counter);
+        bytes.extend_from_slice(&hasher.finish().to_le_bytes());
+        counter += 1;
+    }
+    bytes.truncate(len);
+    Ok(bytes)
+}
+
+/// 新建AES-2扩展字段，actual为加密前的压缩算法
+pub(crate) fn aes_extra(strength: AesStrength, actual: CompressionMethod) -> Extra {
+    Extra::Aes {
+        version: 2,
+        vendor: AES_VENDOR_ID,
+        strength: strength as u8,
+        compression_method: actual,
+    }
+}
+
+#[cfg(feature = "use_aes")]
+mod aes_impl {
+    use super::{AUTH_CODE_SIZE, AesStrength, VERIFIER_SIZE};
+    use aes::cipher::{BlockCipherEncrypt, KeyInit};
+    use hmac::{Hmac, Mac};
+    use sha1::Sha1;
+
+    const PBKDF2_ROUNDS: u32 = 1000;
+
+    enum AesCipher {
+        Aes128(aes::Aes128),
+        Aes192(aes::Aes192),
+        Aes256(aes::Aes256),
+    }
+    impl AesCipher {
+        fn encrypt_block(&self, block: &mut [u8; 16]) {
+            match self {
+                Self::Aes128(cipher) => cipher.encrypt_block(block.into()),
+                Self::Aes192(cipher) => cipher.encrypt_block(block.into()),
+                Self::Aes256(cipher) => cipher.encrypt_block(block.into()),
+            }
+        }
+    }
+
+    // WinZip使用从1开始的小端计数器的CTR模式
+    pub struct AesCtr {
+        cipher: AesCipher,
+        counter: u128,
+        keystream: [u8; 16],
+        used: usize,
+        mac: Hmac<Sha1>,
+    }
+    impl AesCtr {
+        /// 派生密钥，返回(加解密状态, 密码校验值)
+        pub fn new(password: &[u8], salt: &[u8], strength: AesStrength) -> (Self, [u8; 2]) {
+            let key_size = strength.key_size();
+            let mut derived = vec![0u8; key_size * 2 + VERIFIER_SIZE];
+            pbkdf2::pbkdf2_hmac::<Sha1>(password, salt, PBKDF2_ROUNDS, &mut derived);
+            let key = &derived[..key_size];
+            let cipher = match strength {
+                AesStrength::Aes128 => AesCipher::Aes128(aes::Aes128::new_from_slice(key).unwrap()),
+                AesStrength::Aes192 => AesCipher::Aes192(aes::Aes192::new_from_slice(key).unwrap()),
+                AesStrength::Aes256 => AesCipher::Aes256(aes::Aes256::new_from_slice(key).unwrap()),
+            };
+            let mac = Hmac::<Sha1>::new_from_slice(&derived[key_size..key_size * 2]).unwrap();
+            let verifier = [derived[key_size * 2], derived[key_size * 2 + 1]];
+            (
+                Self {
+                    cipher,
+                    counter: 0,
+                    keystream: [0u8; 16],
+                    used: 16,
+                    mac,
+                },
+                verifier,
+            )
+        }
+        fn apply(&mut self, data: &mut [u8]) {
+            for byte in data {
+                if self.used == 16 {
+                    self.counter = self.counter.wrapping_add(1);
+                    self.keystream = self.counter.to_le_bytes();
+                    self.cipher.encrypt_block(&mut self.keystream);
+                    self.used = 0;
+                }
+                *byte ^= self.keystream[self.used];
+                self.used += 1;
+            }
+        }
+        pub fn encrypt(&mut self, data: &mut [u8]) {
+            self.apply(data);
+            self.mac.update(data);
+        }
+        pub fn decrypt(&mut self, data: &mut [u8]) {
+            self.mac.update(data);
+            self.apply(data);
+        }
+        pub fn auth_code(self) -> [u8; AUTH_CODE_SIZE] {
+            let code = self.mac.finalize().into_bytes();
+            let mut value = [0u8; AUTH_CODE_SIZE];
+            value.copy_from_slice(&code[..AUTH_CODE_SIZE]);
+            value
+        }
+    }
+}
+
+// 传统PKWARE加密的三个密钥
+#[derive(Clone)]
+struct ZipCryptoKeys([u32; 3]);
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut value = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if value & 1 != 0 {
+                0xEDB88320 ^ (value >> 1)
+            } else {
+                value >> 1
+            };
+            bit += 1;
+        }
+        table[index] = value;
+        index += 1;
+    }
+    table
+}
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+impl ZipCryptoKeys {
+    fn new(password: &[u8]) -> Self {
+        let mut keys = Self([0x12345678, 0x23456789, 0x34567890]);
+        for byte in password {
+            keys.update(*byte);
+        }
+        keys
+    }
+    fn crc32(crc: u32, byte: u8) -> u32 {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    }
+    fn update(&mut self, byte: u8) {
+        self.0[0] = Self::crc32(self.0[0], byte);
+        self.0[1] = (self.0[1].wrapping_add(self.0[0] & 0xFF))
+            .wrapping_mul(134775813)
+            .wrapping_add(1);
+        self.0[2] = Self::crc32(self.0[2], (self.0[1] >> 24) as u8);
+    }
+    fn stream_byte(&self) -> u8 {
+        let temp = (self.0[2] | 2) as u16;
+        (temp.wrapping_mul(temp ^ 1) >> 8) as u8
+    }
+    fn encrypt(&mut self, data: &mut [u8]) {
+        for byte in data {
+            let plain = *byte;
+            *byte ^= self.stream_byte();
+            self.update(plain);
+        }
+    }
+    fn decrypt(&mut self, data: &mut [u8]) {
+        for byte in data {
+            *byte ^= self.stream_byte();
+            self.update(*byte);
+        }
+    }
+}
+
+/// 传统加密的12字节头部
+pub const ZIP_CRYPTO_HEADER_SIZE: u64 = 12;
+
+/// 边读边解密，创建时读取并校验加密头部(AES为盐值和密码校验值，传统加密为12字节头部)，
+/// 读完密文后校验AES认证码。AES-CTR和传统加密都是流密码，不需要缓存整个条目
+pub(crate) struct Decryptor {
+    cipher: Option<Cipher>,
+    // 剩余的密文长度，不含认证码
+    remain: u64,
+    name: String,
+}
+impl Decryptor {
+    /// length为加密数据的总长度
+    #[cfg_attr(not(feature = "use_aes"), allow(unused_variables))]
+    pub(crate) fn aes<'a, R>(
+        reader: &'a mut R,
+        length: u64,
+        password: &'a [u8],
+        strength: AesStrength,
+        name: &'a str,
+    ) -> impl Future<Output = BinResult<Self>> + Send + 'a
+    where
+        R: Read + Send,
+    {
+        async move {
+            #[cfg(feature = "use_aes")]
+            {
+                let remain = length.checked_sub(strength.overhead()).ok_or_else(|| {
+                    Error::AssertFail(format!("encrypted data of {} is too short", name))
+                })?;
+                let mut salt = vec![0u8; strength.salt_size()];
+                reader.read_exact(&mut salt).await?;
+                let mut verifier = [0u8; VERIFIER_SIZE];
+                reader.read_exact(&mut verifier).await?;
+                let (ctr, expected) = aes_impl::AesCtr::new(password, &salt, strength);
+                if verifier != expected {
+                    return Err(PasswordError::Incorrect(name.to_string()).into_error());
+                }
+                Ok(Self {
+                    cipher: Some(Cipher::Aes(ctr)),
+                    remain,
+                    name: name.to_string(),
+                })
+            }
+            #[cfg(not(feature = "use_aes"))]
+            {
+                Err(PasswordError::Unsupported(name.to_string()).into_error())
+            }
+        }
+    }
+    /// check为头部最后一字节的校验值(CRC高8位或有数据描述符时的修改时间高8位)
+    pub(crate) fn zip_crypto<'a, R>(
+        reader: &'a mut R,
+        length: u64,
+        password: &'a [u8],
+        check: u8,
+        name: &'a str,
+    ) -> impl Future<Output = BinResult<Self>> + Send + 'a
+    where
+        R: Read + Send,
+    {
+        async move {
+            let remain = length.checked_sub(ZIP_CRYPTO_HEADER_SIZE).ok_or_else(|| {
+                Error::AssertFail(format!("encrypted data of {} is too short", name))
+            })?;
+            let mut keys = ZipCryptoKeys::new(password);
+            let mut header = [0u8; ZIP_CRYPTO_HEADER_SIZE as usize];
+            reader.read_exact(&mut header).await?;
+            keys.decrypt(&mut header);
+            if header[11] != check {
+                return Err(PasswordError::Incorrect(name.to_string()).into_error());
+            }
+            Ok(Self {
+                cipher: Some(Cipher::ZipCrypto(keys)),
+                remain,
+                name: name.to_string(),
+            })
+        }
+    }
+    /// 解密后的长度
+    pub(crate) fn remain(&self) -> u64 {
+        self.remain
+    }
+    /// 从reader读取并解密到buf，返回0表示密文已读完并通过校验。
+    /// 认证码不匹配时返回包含PasswordError的io::Error
+    pub(crate) fn read<'a, R>(
+        &'a mut self,
+        reader: &'a mut R,
+        buf: &'a mut [u8],
+    ) -> impl Future<Output = std::io::Result<usize>> + Send + 'a
+    where
+        R: Read + Send,
+    {
+        async move {
+            if self.remain == 0 {
+                self.verify(reader).await?;
+                return Ok(0);
+            }
+            let len = self.remain.min(buf.len() as u64) as usize;
+            let len = reader.read(&mut buf[..len]).await?;
+            if len == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    format!("encrypted data of {} is truncated", self.name),
+                ));
+            }
+            match &mut self.cipher {
+                #[cfg(feature = "use_aes")]
+                Some(Cipher::Aes(ctr)) => ctr.decrypt(&mut buf[..len]),
+                Some(Cipher::ZipCrypto(keys)) => keys.decrypt(&mut buf[..len]),
+                None => {}
+            }
+            self.remain -= len as u64;
+            Ok(len)
+        }
+    }
+    #[cfg_attr(not(feature = "use_aes"), allow(unused_variables))]
+    fn verify<'a, R>(
+        &'a mut self,
+        reader: &'a mut R,
+    ) -> impl Future<Output = std::io::Result<()>> + Send + 'a
+    where
+        R: Read + Send,
+    {
+        async move {
+            #[cfg(feature = "use_aes")]
+            if let Some(Cipher::Aes(ctr)) = self.cipher.take() {
+                let mut auth_code = [0u8; AUTH_CODE_SIZE];
+                reader.read_exact(&mut auth_code).await?;
+                if auth_code != ctr.auth_code() {
+                    return Err(std::io::Error::other(PasswordError::AuthenticationFailed(
+                        self.name.clone(),
+                    )));
+                }
+            }
+            Ok(())
+        }
+    }
+    /// 解密全部数据写入writer
+    pub(crate) fn copy<'a, R, W>(
+        &'a mut self,
+        reader: &'a mut R,
+        writer: &'a mut W,
+    ) -> impl Future<Output = BinResult<()>> + Send + 'a
+    where
+        R: Read + Send,
+        W: Write + Send,
+    {
+        async move {
+            let mut buffer = vec![0u8; 64 * 1024];
+            loop {
+                let len = self
+                    .read(reader, &mut buffer)
+                    .await
+                    .map_err(map_password_error)?;
+                if len == 0 {
+                    return Ok(());
+                }
+                writer.write_all(&buffer[..len]).await?;
+            }
+        }
+    }
+}
+
+/// Decryptor读取时产生的错误包装在io::Error中，取出后作为PasswordError返回
+pub(crate) fn map_password_error(error: std::io::Error) -> Error {
+    if error
+        .get_ref()
+        .is_some_and(|inner| inner.is::<PasswordError>())
+    {
+        match error
+            .into_inner()
+            .map(|inner| inner.downcast::<PasswordError>())
+        {
+            Some(Ok(password)) => return password.into_error(),
+            _ => return Error::AssertFail("password error lost".to_string()),
+        }
+    }
+    Error::Io(error)
+}
+
+enum Cipher {
+    #[cfg(feature = "use_aes")]
+    Aes(aes_impl::AesCtr),
+    ZipCrypto(ZipCryptoKeys),
+}
+
+/// 加密写入，先写出加密头部(AES为盐值和密码校验值)，finish时写出AES认证码
+pub(crate) struct EncryptWriter<'a, W> {
+    inner: &'a mut W,
+    cipher: Cipher,
+    buffer: Vec<u8>,
+}
+impl<'a, W> EncryptWriter<'a, W>
+where
+    W: Write + Seek + Send,
+{
+    // check为传统加密头部最后一字节的校验值
+    #[cfg_attr(feature = "use_aes", allow(unused_variables))]
+    pub(crate) fn new(
+        inner: &'a mut W,
+        password: &'a [u8],
+        encryption: Encryption,
+        check: u8,
+        name: &'a str,
+    ) -> impl Future<Output = BinResult<Self>> + Send + 'a {
+        async move {
+            let cipher = match encryption {
+                #[cfg(feature = "use_aes")]
+                Encryption::Aes(strength) => {
+                    let salt = random_bytes(strength.salt_size())?;
+                    let (ctr, verifier) = aes_impl::AesCtr::new(password, &salt, strength);
+                    inner.write_all(&salt).await?;
+                    inner.write_all(&verifier).await?;
+                    Cipher::Aes(ctr)
+                }
+                #[cfg(not(feature = "use_aes"))]
+                Encryption::Aes(_) => {
+                    return Err(PasswordError::Unsupported(name.to_string()).into_error());
+                }
+                Encryption::ZipCrypto => {
+                    let mut keys = ZipCryptoKeys::new(password);
+                    let mut header = random_bytes(ZIP_CRYPTO_HEADER_SIZE as usize)?;
+                    header[11] = check;
+                    keys.encrypt(&mut header);
+                    inner.write_all(&header).await?;
+                    Cipher::ZipCrypto(keys)
+                }
+            };
+            Ok(Self {
+                inner,
+                cipher,
+                buffer: vec![],
+            })
+        }
+    }
+    pub(crate) fn finish(self) -> impl Future<Output = BinResult<()>> + Send + 'a {
+        async move {
+            match self.cipher {
+                #[cfg(feature = "use_aes")]
+                Cipher::Aes(ctr) => {
+                    let auth_code = ctr.auth_code();
+                    self.inner.write_all(&auth_code).await?;
+                }
+                Cipher::ZipCrypto(_) => {}
+            }
+            Ok(())
+        }
+    }
+}
+impl<W> Write for EncryptWriter<'_, W>
+where
+    W: Write + Seek + Send,
+{
+    fn write(&mut self, buf: &[u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            self.buffer.clear();
+            self.buffer.extend_from_slice(buf);
+            match &mut self.cipher {
+                #[cfg(feature = "use_aes")]
+                Cipher::Aes(ctr) => ctr.encrypt(&mut self.buffer),
+                Cipher::ZipCrypto(keys) => keys.encrypt(&mut self.buffer),
+            }
+            self.inner.write_all(&self.buffer).await?;
+            Ok(buf.len())
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move { self.inner.flush().await }
+    }
+}
+impl<W> Seek for EncryptWriter<'_, W>
+where
+    W: Write + Seek + Send,
+{
+    fn seek(
+        &mut self,
+        pos: std::io::SeekFrom,
+    ) -> impl Future<Output = std::io::Result<u64>> + Send {
+        async move {
+            // 密文是流式生成的，只支持查询当前位置
+            if pos != std::io::SeekFrom::Current(0) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "encrypted writer can't seek",
+                ));
+            }
+            self.inner.seek(pos).await
+        }
+    }
+}
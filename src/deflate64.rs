@@ -0,0 +1,511 @@
+use crate::codec::Decoder;
+
+// Deflate64与Deflate的区别: 64K窗口, 长度码285为3+16位额外值, 距离码30/31可用
+const WINDOW_SIZE: usize = 64 * 1024;
+const WINDOW_MASK: usize = WINDOW_SIZE - 1;
+// 内部缓存的未解码输入超过该值时暂停接收新输入
+const MAX_PENDING_INPUT: usize = 64 * 1024;
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 3,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 16,
+];
+const DIST_BASE: [u16; 32] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577, 32769, 49153,
+];
+const DIST_EXTRA: [u8; 32] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13, 14, 14,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+enum Fail {
+    // 输入不足，回滚到检查点等待更多数据
+    Input,
+    Data(&'static str),
+}
+
+type Step<T> = Result<T, Fail>;
+
+// 规范Huffman查找表: 以低位在前的bits位输入为下标，值为 符号<<4 | 码长，0表示无效码
+struct Huffman {
+    table: Vec<u16>,
+    bits: u32,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Step<Self> {
+        let mut count = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            count[len as usize] += 1;
+        }
+        count[0] = 0;
+        let mut left: i32 = 1;
+        for &len_count in &count[1..] {
+            left <<= 1;
+            left -= len_count as i32;
+            if left < 0 {
+                return Err(Fail::Data("over-subscribed huffman code"));
+            }
+        }
+        let bits = (1..=MAX_BITS)
+            .rev()
+            .find(|&len| count[len] > 0)
+            .unwrap_or(0);
+        let mut next = [0u32; MAX_BITS + 1];
+        let mut code = 0u32;
+        for len in 1..=MAX_BITS {
+            code = (code + count[len - 1] as u32) << 1;
+            next[len] = code;
+        }
+        let mut table = vec![0u16; 1 << bits];
+        for (value, &len) in lengths.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let len = len as usize;
+            //码按高位在前定义，输入按低位在前读取，填表前翻转
+            let reversed = (next[len].reverse_bits() >> (32 - len)) as usize;
+            next[len] += 1;
+            let entry = (value as u16) << 4 | len as u16;
+            for index in (reversed..table.len()).step_by(1 << len) {
+                table[index] = entry;
+            }
+        }
+        Ok(Self {
+            table,
+            bits: bits as u32,
+        })
+    }
+}
+
+enum State {
+    Header,
+    Stored(usize),
+    Codes(Huffman, Huffman),
+    Done,
+}
+
+pub struct Deflate64Decoder {
+    input: Vec<u8>,
+    pos: usize,
+    bit_buf: u64,
+    bit_count: u32,
+    state: State,
+    last: bool,
+    window: Vec<u8>,
+    window_pos: usize,
+    total_out: u64,
+    // 未完成的匹配复制 (长度, 距离)
+    copy: (usize, usize),
+}
+
+impl Deflate64Decoder {
+    pub fn new() -> Self {
+        Self {
+            input: vec![],
+            pos: 0,
+            bit_buf: 0,
+            bit_count: 0,
+            state: State::Header,
+            last: false,
+            window: vec![0u8; WINDOW_SIZE],
+            window_pos: 0,
+            total_out: 0,
+            copy: (0, 0),
+        }
+    }
+    fn checkpoint(&self) -> (usize, u64, u32) {
+        (self.pos, self.bit_buf, self.bit_count)
+    }
+    fn rollback(&mut self, (pos, bit_buf, bit_count): (usize, u64, u32)) {
+        self.pos = pos;
+        self.bit_buf = bit_buf;
+        self.bit_count = bit_count;
+    }
+    fn refill(&mut self) {
+        while self.bit_count <= 56 {
+            let Some(&byte) = self.input.get(self.pos) else {
+                break;
+            };
+            self.pos += 1;
+            self.bit_buf |= (byte as u64) << self.bit_count;
+            self.bit_count += 8;
+        }
+    }
+    // 把位缓存中完整的字节退回输入，只保留不足一字节的位
+    fn unread_bytes(&mut self) {
+        let bytes = self.bit_count / 8;
+        self.pos -= bytes as usize;
+        self.bit_count -= bytes * 8;
+        self.bit_buf &= (1u64 << self.bit_count) - 1;
+    }
+    fn bits(&mut self, need: u32) -> Step<u32> {
+        if self.bit_count < need {
+            self.refill();
+            if self.bit_count < need {
+                return Err(Fail::Input);
+            }
+        }
+        let value = self.bit_buf & ((1u64 << need) - 1);
+        self.bit_buf >>= need;
+        self.bit_count -= need;
+        Ok(value as u32)
+    }
+    fn decode_symbol(&mut self, huffman: &Huffman) -> Step<u16> {
+        if self.bit_count < huffman.bits {
+            self.refill();
+        }
+        let index = self.bit_buf & ((1u64 << huffman.bits) - 1);
+        let entry = huffman.table[index as usize];
+        let len = (entry & 0xF) as u32;
+        if len == 0 || len > self.bit_count {
+            //位数不足时补零查表的结果不可信
+            return Err(if self.bit_count < huffman.bits {
+                Fail::Input
+            } else {
+                Fail::Data("invalid huffman code")
+            });
+        }
+        self.bit_buf >>= len;
+        self.bit_count -= len;
+        Ok(entry >> 4)
+    }
+    fn block_header(&mut self) -> Step<State> {
+        self.last = self.bits(1)? == 1;
+        match self.bits(2)? {
+            0 => {
+                //存储块按字节对齐，数据直接从输入复制
+                self.bits(self.bit_count % 8)?;
+                self.unread_bytes();
+                let len = self.bits(16)?;
+                let nlen = self.bits(16)?;
+                if len != !nlen & 0xFFFF {
+                    return Err(Fail::Data("stored block length mismatch"));
+                }
+                self.unread_bytes();
+                Ok(State::Stored(len as usize))
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                Ok(State::Codes(
+                    Huffman::new(&lengths)?,
+                    Huffman::new(&[5u8; 32])?,
+                ))
+            }
+            2 => self.dynamic_tables(),
+            _ => Err(Fail::Data("invalid block type")),
+        }
+    }
+    fn dynamic_tables(&mut self) -> Step<State> {
+        let literal_count = self.bits(5)? as usize + 257;
+        let dist_count = self.bits(5)? as usize + 1;
+        let code_count = self.bits(4)? as usize + 4;
+        if literal_count > 286 {
+            return Err(Fail::Data("too many length codes"));
+        }
+        let mut lengths = [0u8; 19];
+        for &index in &CODE_LENGTH_ORDER[..code_count] {
+            lengths[index] = self.bits(3)? as u8;
+        }
+        let code_huffman = Huffman::new(&lengths)?;
+        let mut lengths = vec![0u8; literal_count + dist_count];
+        let mut index = 0;
+        while index < lengths.len() {
+            let symbol = self.decode_symbol(&code_huffman)?;
+            if symbol < 16 {
+                lengths[index] = symbol as u8;
+                index += 1;
+                continue;
+            }
+            let (value, repeat) = match symbol {
+                16 => {
+                    if index == 0 {
+                        return Err(Fail::Data("repeat with no first length"));
+                    }
+                    (lengths[index - 1], 3 + self.bits(2)? as usize)
+                }
+                17 => (0, 3 + self.bits(3)? as usize),
+                _ => (0, 11 + self.bits(7)? as usize),
+            };
+            if index + repeat > lengths.len() {
+                return Err(Fail::Data("too many code lengths"));
+            }
+            lengths[index..index + repeat].fill(value);
+            index += repeat;
+        }
+        if lengths[256] == 0 {
+            return Err(Fail::Data("missing end of block code"));
+        }
+        Ok(State::Codes(
+            Huffman::new(&lengths[..literal_count])?,
+            Huffman::new(&lengths[literal_count..])?,
+        ))
+    }
+    // 返回None表示块结束，否则为字面量或(长度, 距离)
+    fn decode_code(
+        &mut self,
+        literal: &Huffman,
+        dist: &Huffman,
+    ) -> Step<Option<Result<u8, (usize, usize)>>> {
+        let symbol = self.decode_symbol(literal)? as usize;
+        if symbol < 256 {
+            return Ok(Some(Ok(symbol as u8)));
+        }
+        if symbol == 256 {
+            return Ok(None);
+        }
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(Fail::Data("invalid length symbol"));
+        }
+        let length =
+            LENGTH_BASE[symbol] as usize + self.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+        let symbol = self.decode_symbol(dist)? as usize;
+        if symbol >= DIST_BASE.len() {
+            return Err(Fail::Data("invalid distance symbol"));
+        }
+        let distance = DIST_BASE[symbol] as usize + self.bits(DIST_EXTRA[symbol] as u32)? as usize;
+        if distance as u64 > self.total_out {
+            return Err(Fail::Data("distance too far back"));
+        }
+        Ok(Some(Err((length, distance))))
+    }
+    fn push(&mut self, byte: u8, output: &mut [u8], out: &mut usize) {
+        output[*out] = byte;
+        *out += 1;
+        self.window[self.window_pos] = byte;
+        self.window_pos = (self.window_pos + 1) & WINDOW_MASK;
+        self.total_out += 1;
+    }
+    fn inflate(&mut self, output: &mut [u8], out: &mut usize) -> Step<()> {
+        loop {
+            while self.copy.0 > 0 && *out < output.len() {
+                let byte = self.window[(self.window_pos + WINDOW_SIZE - self.copy.1) & WINDOW_MASK];
+                self.push(byte, output, out);
+                self.copy.0 -= 1;
+            }
+            if *out == output.len() {
+                return Ok(());
+            }
+            let checkpoint = self.checkpoint();
+            let result = match std::mem::replace(&mut self.state, State::Done) {
+                State::Done => return Ok(()),
+                State::Header => match self.block_header() {
+                    Ok(next) => {
+                        self.state = next;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        self.state = State::Header;
+                        Err(e)
+                    }
+                },
+                State::Stored(remaining) => {
+                    let len = remaining
+                        .min(self.input.len() - self.pos)
+                        .min(output.len() - *out);
+                    for index in self.pos..self.pos + len {
+                        self.push(self.input[index], output, out);
+                    }
+                    self.pos += len;
+                    if remaining == len {
+                        self.state = self.next_block();
+                        Ok(())
+                    } else {
+                        self.state = State::Stored(remaining - len);
+                        if len == 0 { Err(Fail::Input) } else { Ok(()) }
+                    }
+                }
+                State::Codes(literal, dist) => {
+                    let result = self.decode_code(&literal, &dist);
+                    self.state = State::Codes(literal, dist);
+                    match result {
+                        Ok(Some(Ok(byte))) => {
+                            self.push(byte, output, out);
+                            Ok(())
+                        }
+                        Ok(Some(Err(copy))) => {
+                            self.copy = copy;
+                            Ok(())
+                        }
+                        Ok(None) => {
+                            self.state = self.next_block();
+                            Ok(())
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+            };
+            if let Err(e) = result {
+                self.rollback(checkpoint);
+                return Err(e);
+            }
+        }
+    }
+    fn next_block(&self) -> State {
+        if self.last {
+            State::Done
+        } else {
+            State::Header
+        }
+    }
+}
+
+impl Decoder for Deflate64Decoder {
+    fn decode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        _eof: bool,
+    ) -> std::io::Result<(usize, usize, bool)> {
+        self.unread_bytes();
+        if self.pos > 0 {
+            self.input.drain(..self.pos);
+            self.pos = 0;
+        }
+        let take = if self.input.len() < MAX_PENDING_INPUT {
+            input.len()
+        } else {
+            0
+        };
+        self.input.extend_from_slice(&input[..take]);
+        let mut out = 0;
+        match self.inflate(output, &mut out) {
+            Ok(()) | Err(Fail::Input) => {}
+            Err(Fail::Data(message)) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    message,
+                ));
+            }
+        }
+        let done = matches!(self.state, State::Done) && self.copy.0 == 0;
+        Ok((take, out, done))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // zlib以Z_FIXED生成的固定Huffman块
+    const FIXED: [u8; 27] = [
+        0x73, 0x49, 0x4d, 0xcb, 0x49, 0x2c, 0x49, 0x35, 0x33, 0x51, 0x48, 0xcb, 0xac, 0x48, 0x4d,
+        0x51, 0x48, 0xca, 0xc9, 0x4f, 0xce, 0xd6, 0xc1, 0xc9, 0xd1, 0x03, 0x00,
+    ];
+    const FIXED_TEXT: &[u8] = b"Deflate64 fixed block, fixed block, fixed block.";
+    // zlib默认策略生成的动态Huffman块
+    const DYNAMIC: [u8; 67] = [
+        0x15, 0xcb, 0x31, 0x12, 0x00, 0x21, 0x0c, 0x42, 0xd1, 0xfb, 0x58, 0x2d, 0x51, 0x63, 0x1c,
+        0x87, 0xfb, 0x5f, 0x6b, 0x7f, 0x9a, 0x47, 0x01, 0x68, 0xc8, 0x7a, 0x1a, 0xe1, 0xc0, 0xe9,
+        0x89, 0xcb, 0x0b, 0xb7, 0x37, 0xa6, 0x13, 0x8f, 0x0f, 0x96, 0x0b, 0xaf, 0xef, 0x0b, 0x5e,
+        0x81, 0xc1, 0x32, 0x78, 0x25, 0x2e, 0xda, 0xe0, 0xa5, 0x8f, 0x48, 0xab, 0xfb, 0x63, 0xf5,
+        0xa0, 0xac, 0x5e, 0x5c, 0xab, 0xde, 0x0f,
+    ];
+    const DYNAMIC_TEXT: &[u8] = b"1*1=1;1*2=2;1*3=3;1*4=4;1*5=5;1*6=6;1*7=7;1*8=8;1*9=9;\
+2*1=2;2*2=4;2*3=6;2*4=8;2*5=10;2*6=12;2*7=14;2*8=16;2*9=18;";
+    // 固定块后跟sync flush产生的空存储块，存储块从字节中间开始
+    const SYNC_FLUSH: [u8; 33] = [
+        0x2a, 0xae, 0xcc, 0x4b, 0x56, 0x48, 0xcb, 0x29, 0x2d, 0xce, 0x50, 0x00, 0x00, 0x00, 0x00,
+        0xff, 0xff, 0x2b, 0xc9, 0x48, 0xcd, 0x53, 0xc8, 0xcd, 0x2f, 0x4a, 0x55, 0x28, 0x49, 0xad,
+        0x28, 0x01, 0x00,
+    ];
+    const SYNC_FLUSH_TEXT: &[u8] = b"sync flush then more text";
+    const STORED: [u8; 22] = [
+        0x01, 0x11, 0x00, 0xee, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x20, 0x62, 0x6c, 0x6f,
+        0x63, 0x6b, 0x20, 0x64, 0x61, 0x74, 0x61,
+    ];
+    // 手工构造的固定块: "abc"，长度码285(额外59997)距离3，
+    // 长度10距离码30(32769+1)，长度20距离码31(49153+100)
+    const LONG_MATCH: [u8; 15] = [
+        0x4b, 0x4c, 0x4a, 0x1e, 0xed, 0x52, 0x47, 0x88, 0x17, 0x00, 0x60, 0xfb, 0x64, 0x00, 0x00,
+    ];
+
+    fn inflate(data: &[u8], chunk: usize, output_size: usize) -> std::io::Result<Vec<u8>> {
+        let mut decoder = Deflate64Decoder::new();
+        let mut result = vec![];
+        let mut output = vec![0u8; output_size];
+        let mut pos = 0;
+        loop {
+            let end = (pos + chunk).min(data.len());
+            let (used, written, done) =
+                decoder.decode(&data[pos..end], &mut output, end == data.len())?;
+            pos += used;
+            result.extend_from_slice(&output[..written]);
+            if done {
+                return Ok(result);
+            }
+            assert!(used > 0 || written > 0, "decoder stalled");
+        }
+    }
+
+    fn long_match_text() -> Vec<u8> {
+        let mut text = b"abc".to_vec();
+        for (length, distance) in [(60000, 3), (10, 32770), (20, 49253)] {
+            for _ in 0..length {
+                text.push(text[text.len() - distance]);
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn fixed_block() {
+        assert_eq!(inflate(&FIXED, FIXED.len(), 4096).unwrap(), FIXED_TEXT);
+    }
+
+    #[test]
+    fn dynamic_block() {
+        assert_eq!(
+            inflate(&DYNAMIC, DYNAMIC.len(), 4096).unwrap(),
+            DYNAMIC_TEXT
+        );
+    }
+
+    #[test]
+    fn stored_block() {
+        assert_eq!(
+            inflate(&STORED, STORED.len(), 4096).unwrap(),
+            b"stored block data"
+        );
+        assert_eq!(
+            inflate(&SYNC_FLUSH, SYNC_FLUSH.len(), 4096).unwrap(),
+            SYNC_FLUSH_TEXT
+        );
+    }
+
+    #[test]
+    fn long_length_and_far_distance() {
+        let text = long_match_text();
+        assert_eq!(text.len(), 60033);
+        assert_eq!(inflate(&LONG_MATCH, LONG_MATCH.len(), 4096).unwrap(), text);
+    }
+
+    #[test]
+    fn byte_at_a_time() {
+        assert_eq!(inflate(&FIXED, 1, 7).unwrap(), FIXED_TEXT);
+        assert_eq!(inflate(&DYNAMIC, 1, 7).unwrap(), DYNAMIC_TEXT);
+        assert_eq!(inflate(&STORED, 1, 7).unwrap(), b"stored block data");
+        assert_eq!(inflate(&SYNC_FLUSH, 1, 7).unwrap(), SYNC_FLUSH_TEXT);
+        assert_eq!(inflate(&LONG_MATCH, 1, 7).unwrap(), long_match_text());
+    }
+
+    #[test]
+    fn distance_too_far_back() {
+        // "a"之后引用距离5
+        let error = inflate(&[0x4b, 0x04, 0x12, 0x00], 4, 4096).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
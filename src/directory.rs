@@ -1,17 +1,35 @@
-use crate::file::{DataDescriptor, ExtraList, ZipFile};
-use crate::hash::{Crc32Reader, HashWriter, HashWriterNull, Hasher};
-use crate::zip::{Config, StreamDefault, ZipModel, is_dir};
+use crate::codec;
+use crate::crypto::{
+    AES_VERSION, Decryptor, EncryptWriter, Encryption, PasswordError, aes_extra, map_password_error,
+};
+use crate::error::ZipError;
+use crate::extra::{Extra, ExtraField, ExtraRegistry};
+use crate::file::{
+    DataDescriptor, ExtraList, ZipFile, decode_name, extra_length, unicode_extra_bytes, utf8_flag,
+    zip64_extra_bytes,
+};
+use crate::hash::{
+    Crc32Reader, Crc32Writer, HashWriter, HashWriterNull, Hasher, NullWriter, checksum_error,
+};
+use crate::limits::{ExtractLimits, LimitError, LimitWriter, map_limit_error};
+use crate::options::ParseOptions;
+use crate::stream::TempData;
+use crate::time::{
+    DosDateTime, EntryTimes, TimeZone, from_ntfs, from_unix_timestamp, to_ntfs, to_unix_timestamp,
+};
+use crate::zip::{Config, StreamDefault, UTF8_FLAG, ZIP64_LIMIT, ZIP64_VERSION, ZipModel, is_dir};
 use binrw::io::bytes::BytesCallback;
 use binrw::io::cb::ReadCallback;
 use binrw::io::read::Read;
 use binrw::io::read::ReadExt;
 use binrw::io::seek::Seek;
 use binrw::io::write::Write;
-use binrw::io::{BufWriter, ReadBytesCallback};
+use binrw::io::{BufReader, BufWriter, ReadBytesCallback};
 use binrw::{BinRead, BinReaderExt, BinResult, BinWrite, BinWriterExt, Endian, Error};
 use miniz_oxide::deflate::CompressionLevel;
-use miniz_oxide::inflate::stream::decompress_stream;
+use std::io::Cursor;
 use std::string::FromUtf8Error;
+use std::time::SystemTime;
 
 // #[binrw]
 // #[brw(repr(u16))]
@@ -30,6 +48,7 @@ pub enum CompressionMethod {
     WavPack = 0x0061,
     PPMd = 0x0062,
     AES = 0x0063,
+    Zstd = 0x005D,
 }
 impl BinWrite for CompressionMethod {
     type Args<'a> = ();
@@ -67,6 +86,7 @@ impl Into<u16> for CompressionMethod {
             Self::WavPack => 0x0061,
             Self::PPMd => 0x0062,
             Self::AES => 0x0063,
+            Self::Zstd => 0x005D,
         }
     }
 }
@@ -98,6 +118,7 @@ impl BinRead for CompressionMethod {
                 0x0061 => Self::WavPack,
                 0x0062 => Self::PPMd,
                 0x0063 => Self::AES,
+                0x005D => Self::Zstd,
                 _ => {
                     return Err(Error::BadMagic(
                         reader.position().await?,
@@ -109,6 +130,20 @@ impl BinRead for CompressionMethod {
         }
     }
 }
+impl CompressionMethod {
+    // 解压该算法所需的最低版本
+    pub fn extract_version(&self) -> u8 {
+        match self {
+            Self::Store => 0x0A,
+            Self::Deflate => 0x14,
+            Self::Deflate64 => 0x15,
+            Self::BZIP2 => 0x2E,
+            Self::AES => 0x33,
+            Self::LZMA | Self::XZ | Self::PPMd | Self::Zstd => 0x3F,
+            _ => 0x14,
+        }
+    }
+}
 // #[binrw]
 // #[br(import(count:u16,))]
 // #[bw()]
@@ -116,6 +151,8 @@ impl BinRead for CompressionMethod {
 pub struct Name {
     // #[br(count = count)]
     pub inner: Vec<u8>,
+    // 按旧代码页解码前的原始字节，写出时原样使用
+    pub raw: Option<Vec<u8>>,
 }
 impl BinWrite for Name {
     type Args<'a> = ();
@@ -123,15 +160,15 @@ impl BinWrite for Name {
     fn write_options<'a, 'w, W>(
         &'a self,
         writer: &'w mut W,
-        endian: Endian,
-        args: Self::Args<'a>,
+        _endian: Endian,
+        _args: Self::Args<'a>,
     ) -> impl Future<Output = BinResult<()>> + Send + 'w
     where
         'a: 'w,
         W: Write + Seek + Send,
         Self: Sync + 'a,
     {
-        async move { writer.write_type_args(&self.inner, endian, args).await }
+        async move { Ok(writer.write_all(self.bytes()).await?) }
     }
 }
 impl BinRead for Name {
@@ -151,6 +188,7 @@ impl BinRead for Name {
             let count = args as u64;
             Ok(Name {
                 inner: reader.read_type_args(endian, (count, ())).await?,
+                raw: None,
             })
         }
     }
@@ -159,6 +197,7 @@ impl From<String> for Name {
     fn from(value: String) -> Self {
         Self {
             inner: value.into_bytes(),
+            raw: None,
         }
     }
 }
@@ -166,10 +205,15 @@ impl From<&str> for Name {
     fn from(value: &str) -> Self {
         Self {
             inner: value.as_bytes().to_vec(),
+            raw: None,
         }
     }
 }
 impl Name {
+    /// 写入头部的字节
+    pub fn bytes(&self) -> &[u8] {
+        self.raw.as_deref().unwrap_or(&self.inner)
+    }
     pub fn into_string(self, _pos: u64) -> BinResult<String> {
         self.clone().try_into().map_err(|e| Error::Err(Box::new(e)))
     }
@@ -181,6 +225,15 @@ impl TryInto<String> for Name {
         String::from_utf8(self.inner)
     }
 }
+// created_os的取值
+pub const HOST_DOS: u8 = 0x00;
+pub const HOST_UNIX: u8 = 0x03;
+// 外部属性低8位的MS-DOS属性
+pub const DOS_READ_ONLY: u8 = 0x01;
+pub const DOS_HIDDEN: u8 = 0x02;
+pub const DOS_SYSTEM: u8 = 0x04;
+pub const DOS_DIRECTORY: u8 = 0x10;
+pub const DOS_ARCHIVE: u8 = 0x20;
 pub struct Directory<T>
 where
     T: Read + Write + Seek + Send + StreamDefault,
@@ -202,9 +255,9 @@ where
     pub last_modification_date: u16,
     pub crc_32_uncompressed_data: u32,
     // #[bw(map = |value| if self.is_dir() {0} else {*value})]
-    pub compressed_size: u32,
+    pub compressed_size: u64,
     // #[bw(map = |value| if self.is_dir() {0} else {*value})]
-    pub uncompressed_size: u32,
+    pub uncompressed_size: u64,
     // #[bw(calc = file_name.inner.len() as u16)]
     // pub file_name_length: u16,
     // #[bw(try_calc = extra_fields.bytes())]
@@ -213,9 +266,9 @@ where
     // pub file_comment_length: u16,
     pub number_of_starts: u16,
     pub internal_file_attributes: u16,
-    // #[bw(calc =  if self.is_dir() { 0x41ED0010_u32 } else { 0x81A40000_u32 })]
-    // pub _external_file_attributes: u32,
-    pub offset_of_local_file_header: u32,
+    // 高16位为Unix权限(created_os为Unix时)，低8位为MS-DOS属性
+    pub external_file_attributes: u32,
+    pub offset_of_local_file_header: u64,
     // #[br(args(file_name_length,))]
     pub file_name: Name,
     // #[br(args(extra_field_length))]
@@ -244,6 +297,18 @@ where
     // )]
     // #[bw(write_with = data_write,args(model,self.is_dir()))]
     pub data: Option<T>,
+    // 条目单独设置的密码，优先于FastZip的设置；打包时不为None则加密该条目
+    pub password: Option<Vec<u8>>,
+    // 为None时使用FastZip的加密方式
+    pub encryption: Option<Encryption>,
+    // 打包为Zstd时使用的压缩级别(1-22)，为None时按压缩等级换算
+    pub zstd_level: Option<i32>,
+    // 解压时检查实际输出大小，由FastZip在每次解压前设置
+    pub limits: Option<ExtractLimits>,
+    // 解压时校验输出的CRC-32和长度
+    pub verify_crc32: bool,
+    // 为false时本地头和数据尚未读取，使用前需要load
+    pub loaded: bool,
 }
 impl<T> BinRead for Directory<T>
 where
@@ -252,9 +317,10 @@ where
 {
     type Args<'a>
         = (
-        u16,
+        u64,
         &'a ZipModel,
         &'a T::Config,
+        &'a ParseOptions,
         &'a mut ReadBytesCallback<'a>,
     )
     where
@@ -271,10 +337,15 @@ where
         Self: Send + 'a,
     {
         async move {
-            let (_index, model, config, read_bytes) = args;
+            let (_index, model, config, options, read_bytes) = args;
             let pos = reader.position().await?;
             let magic: u32 = reader.read_le().await?;
-            assert_eq!(magic, 0x02014b50_u32);
+            if magic != 0x02014b50_u32 {
+                return Err(Error::BadMagic(
+                    pos,
+                    format!("magic {} not match for central directory", magic),
+                ));
+            }
             let created_zip_spec: u8 = reader.read_le().await?;
             let created_os: u8 = reader.read_le().await?;
             let extract_zip_spec: u8 = reader.read_le().await?;
@@ -290,95 +361,68 @@ where
             };
             let last_modification_time: u16 = reader.read_le().await?;
             let last_modification_date: u16 = reader.read_le().await?;
-            let mut crc_32_uncompressed_data: u32 = reader.read_le().await?;
-            let mut compressed_size: u32 = reader.read_le().await?;
-            let uncompressed_size: u32 = reader.read_le().await?;
+            let crc_32_uncompressed_data: u32 = reader.read_le().await?;
+            let mut compressed_size = reader.read_le::<u32>().await? as u64;
+            let mut uncompressed_size = reader.read_le::<u32>().await? as u64;
             let file_name_length: u16 = reader.read_le().await?;
             let extra_field_length: u16 = reader.read_le().await?;
             let file_comment_length: u16 = reader.read_le().await?;
             let number_of_starts: u16 = reader.read_le().await?;
             let internal_file_attributes: u16 = reader.read_le().await?;
-            let _external_file_attributes: u32 = reader.read_le().await?;
-            let offset_of_local_file_header: u32 = reader.read_le().await?;
+            let external_file_attributes: u32 = reader.read_le().await?;
+            let mut offset_of_local_file_header = reader.read_le::<u32>().await? as u64;
+            if let Some(limit) = options.limits.max_name_length
+                && file_name_length as usize > limit
+            {
+                return Err(LimitError::NameTooLong {
+                    length: file_name_length as usize,
+                    limit,
+                }
+                .into_error());
+            }
             let file_name: Name = reader.read_le_args(file_name_length).await?;
-            let file_name_str = String::from_utf8_lossy(&file_name.inner)
-                .to_string()
-                .replace("\\", "/");
-            let file_name = Name {
-                inner: file_name_str.as_bytes().to_vec(),
-            };
-            let extra_fields: ExtraList = reader.read_le_args(extra_field_length).await?;
-            let file_comment: Vec<u8> = reader
+            let mut extra_fields: ExtraList = reader.read_le_args(extra_field_length).await?;
+            extra_fields.resolve(&options.extra_registry);
+            let file_name = decode_name(
+                &file_name.inner,
+                flags,
+                &extra_fields,
+                options.name_encoding,
+            );
+            extra_fields.resolve_zip64(
+                &mut uncompressed_size,
+                &mut compressed_size,
+                Some(&mut offset_of_local_file_header),
+            )?;
+            let mut file_comment: Vec<u8> = reader
                 .read_le_args((file_comment_length as u64, ()))
                 .await?;
-            let mut file: ZipFile = zip_file_parse(
-                reader,
-                endian,
-                &model,
-                offset_of_local_file_header,
+            if flags & UTF8_FLAG == 0
+                && let Some(comment) = extra_fields.unicode_comment(&file_comment)
+            {
+                file_comment = comment;
+            }
+            read_bytes(reader.position().await?.saturating_sub(pos)).await?;
+            //只读中央目录时本地头先按中央目录填充
+            let file = ZipFile {
+                extract_zip_spec,
+                extract_os,
+                flags,
+                compression_method: compression_method.clone(),
+                last_modification_time,
+                last_modification_date,
+                crc_32_uncompressed_data,
+                compressed_size,
                 uncompressed_size,
-            )
-            .await?;
-            read_bytes(reader.position().await? - pos).await?;
-            // reader.seek(SeekFrom::Start(pos))?;
-            let data = if is_dir(&file_name.inner) {
-                T::from_config(config).await?
-            } else {
-                if *model == ZipModel::Bin {
-                    let length: u64 = reader.read_type(endian).await?;
-                    let mut data = reader.take(length);
-                    let mut writer = T::from_config(config).await?;
-                    binrw::io::copy(&mut data, &mut writer).await?;
-                    writer.seek_start().await?;
-                    read_bytes(length).await?;
-                    writer
-                } else {
-                    let pos = reader.position().await?;
-                    if *model == ZipModel::Parse {
-                        reader.set_position(file.data_position).await?;
-                    }
-                    let config_pos = reader.position().await?;
-                    let mut config = config.clone();
-                    config.compress_size_mut(compressed_size as u64);
-                    config.un_compress_size_mut(uncompressed_size as u64);
-                    let (mut data, need_copy) =
-                        T::from_link_config(config_pos, compressed_size as u64, &config).await?;
-                    if need_copy {
-                        let mut take_reader = reader.take(compressed_size as u64);
-                        let mut buffer = vec![0u8; 1024 * 8];
-                        loop {
-                            let len = take_reader.read(&mut buffer).await?;
-                            if len == 0 {
-                                break;
-                            }
-                            data.write_all(&buffer[..len]).await?;
-                            read_bytes(len as u64).await?;
-                        }
-                    } else {
-                        reader.seek_relative(compressed_size as i64).await?;
-                        read_bytes(compressed_size as u64).await?;
-                    }
-                    data.seek_start().await?;
-                    let data_descriptor: Option<DataDescriptor> = if file.flags & 0x0008 != 0 {
-                        //TODO数据是流式的
-                        Some(reader.read_le().await?)
-                    } else {
-                        None
-                    };
-                    if let Some(data_descriptor) = &data_descriptor {
-                        compressed_size = data_descriptor.compressed_size;
-                        crc_32_uncompressed_data = data_descriptor.crc32;
-                        file.compressed_size = data_descriptor.compressed_size;
-                        file.crc_32_uncompressed_data = crc_32_uncompressed_data;
-                    }
-                    file.data_descriptor = data_descriptor;
-                    if *model == ZipModel::Parse {
-                        reader.set_position(pos).await?;
-                    }
-                    data
-                }
+                file_name_length,
+                extra_field_length: 0,
+                file_name: file_name.clone(),
+                raw_file_name: file_name.bytes().to_vec(),
+                extra_fields: vec![].into(),
+                data_descriptor: None,
+                data_position: 0,
             };
-            Ok(Self {
+            let mut dir = Self {
                 created_zip_spec,
                 created_os,
                 extract_zip_spec,
@@ -394,13 +438,27 @@ where
                 uncompressed_size,
                 number_of_starts,
                 internal_file_attributes,
+                external_file_attributes,
                 offset_of_local_file_header,
                 file_name,
                 extra_fields,
                 file_comment,
                 file,
-                data: Some(data),
-            })
+                data: None,
+                loaded: false,
+                password: None,
+                encryption: None,
+                zstd_level: None,
+                limits: None,
+                verify_crc32: true,
+            };
+            if !(options.lazy && *model == ZipModel::Parse) {
+                let result = dir
+                    .load_local(reader, endian, model, config, options, read_bytes)
+                    .await;
+                result.map_err(|e| dir.entry_error(e))?;
+            }
+            Ok(dir)
         }
     }
 }
@@ -410,7 +468,7 @@ where
     T::Config: Config,
 {
     type Args<'a>
-        = (&'a ZipModel,)
+        = (&'a ZipModel, bool)
     where
         T: 'a;
 
@@ -427,25 +485,57 @@ where
     {
         async move {
             // let mut writer = BufWriter::new(writer);
-            let (model,) = args;
+            let (model, force_zip64) = args;
+            let compressed_size = if self.is_dir() {
+                0_u64
+            } else {
+                self.compressed_size
+            };
+            let uncompressed_size = if self.is_dir() {
+                0_u64
+            } else {
+                self.uncompressed_size
+            };
+            let zip64_uncompressed_size = force_zip64 || uncompressed_size >= ZIP64_LIMIT;
+            let zip64_compressed_size = force_zip64 || compressed_size >= ZIP64_LIMIT;
+            let zip64_offset = force_zip64 || self.offset_of_local_file_header >= ZIP64_LIMIT;
+            let zip64 = zip64_uncompressed_size || zip64_compressed_size || zip64_offset;
+            let mut extra_bytes = zip64_extra_bytes(
+                zip64_uncompressed_size.then_some(uncompressed_size),
+                zip64_compressed_size.then_some(compressed_size),
+                zip64_offset.then_some(self.offset_of_local_file_header),
+            )
+            .await?;
+            extra_bytes
+                .extend(unicode_extra_bytes(&self.file_name, Some(&self.file_comment)).await?);
+            extra_bytes.extend(self.extra_fields.bytes().await?);
+            let extra_field_length = extra_length(&extra_bytes)?;
+
             writer.write_le(&0x02014b50_u32).await?;
             writer.write_le(&self.created_zip_spec).await?;
             writer.write_le(&self.created_os).await?;
-            writer.write_le(&self.extract_zip_spec).await?;
+            let extract_zip_spec = if zip64 {
+                self.extract_zip_spec.max(ZIP64_VERSION)
+            } else {
+                self.extract_zip_spec
+            };
+            writer.write_le(&extract_zip_spec).await?;
             writer.write_le(&self.extract_os).await?;
             let flags = if is_dir(&self.file_name.inner) {
                 0
             } else {
-                self.flags
+                self.flags & !UTF8_FLAG
             };
-            writer.write_le(&flags).await?; //flags
-            writer
-                .write_le(if self.uncompressed_size == 0 {
-                    &CompressionMethod::Store
-                } else {
-                    &self.compression_method
-                })
-                .await?;
+            writer.write_le(&(flags | self.utf8_flag())).await?; //flags
+            //空文件写为Store，加密的空文件仍有盐值和认证码
+            let compression_method = if self.uncompressed_size == 0
+                && self.compression_method != CompressionMethod::AES
+            {
+                &CompressionMethod::Store
+            } else {
+                &self.compression_method
+            };
+            writer.write_le(compression_method).await?;
             if *model == ZipModel::Bin {
                 writer.write_le(&self.compressed).await?;
                 writer.write_le(&self.sha_value).await?;
@@ -453,34 +543,23 @@ where
             writer.write_le(&self.last_modification_time).await?;
             writer.write_le(&self.last_modification_date).await?;
             writer.write_le(&self.crc_32_uncompressed_data).await?;
-            let compressed_size = if self.is_dir() {
-                0_u32
-            } else {
-                self.compressed_size
-            };
-            writer.write_le(&compressed_size).await?;
-            let uncompressed_size = if self.is_dir() {
-                0_u32
-            } else {
-                self.uncompressed_size
-            };
-            writer.write_le(&uncompressed_size).await?;
             writer
-                .write_le(&(self.file_name.inner.len() as u16))
+                .write_le(&(compressed_size.min(ZIP64_LIMIT) as u32))
+                .await?;
+            writer
+                .write_le(&(uncompressed_size.min(ZIP64_LIMIT) as u32))
                 .await?;
-            let extra_bytes = self.extra_fields.bytes().await?;
-            writer.write_le(&(extra_bytes.len() as u16)).await?;
+            writer
+                .write_le(&(self.file_name.bytes().len() as u16))
+                .await?;
+            writer.write_le(&extra_field_length).await?;
             writer.write_le(&(self.file_comment.len() as u16)).await?;
             writer.write_le(&self.number_of_starts).await?;
             writer.write_le(&self.internal_file_attributes).await?;
+            writer.write_le(&self.external_file_attributes).await?;
             writer
-                .write_le(if self.is_dir() {
-                    &0x41ED0010_u32
-                } else {
-                    &0x81A40000_u32
-                })
+                .write_le(&(self.offset_of_local_file_header.min(ZIP64_LIMIT) as u32))
                 .await?;
-            writer.write_le(&self.offset_of_local_file_header).await?;
             writer.write_le(&self.file_name).await?;
             writer.write_all(&extra_bytes).await?;
             // writer.write_le(&self.extra_fields).await?;
@@ -534,12 +613,19 @@ where
                     uncompressed_size: self.uncompressed_size,
                     number_of_starts: self.number_of_starts,
                     internal_file_attributes: self.internal_file_attributes,
+                    external_file_attributes: self.external_file_attributes,
                     offset_of_local_file_header: self.offset_of_local_file_header,
                     file_name: self.file_name.clone(),
                     extra_fields: self.extra_fields.clone(),
                     file_comment: self.file_comment.clone(),
                     file: self.file.clone(),
                     data: Some(new_data),
+                    password: self.password.clone(),
+                    encryption: self.encryption,
+                    zstd_level: self.zstd_level,
+                    limits: self.limits.clone(),
+                    verify_crc32: self.verify_crc32,
+                    loaded: self.loaded,
                 })
             } else {
                 Err(Error::AssertFail("directory data is none".to_string()))
@@ -555,6 +641,167 @@ where
     pub fn is_dir(&self) -> bool {
         crate::zip::is_dir(&self.file_name.inner)
     }
+    /// Unix权限和文件类型(st_mode)，只有Unix创建且高16位非0时存在
+    pub fn unix_mode(&self) -> Option<u32> {
+        let mode = self.external_file_attributes >> 16;
+        (self.created_os == HOST_UNIX && mode != 0).then_some(mode)
+    }
+    /// 设置Unix权限，created_os同时改为Unix，并同步只读和目录属性
+    pub fn set_unix_mode(&mut self, mode: u32) {
+        let mut dos = self.dos_attributes() & !(DOS_READ_ONLY | DOS_DIRECTORY);
+        if mode & 0o222 == 0 {
+            dos |= DOS_READ_ONLY;
+        }
+        if mode & 0o170000 == 0o040000 {
+            dos |= DOS_DIRECTORY;
+        }
+        self.created_os = HOST_UNIX;
+        self.external_file_attributes = ((mode & 0xFFFF) << 16) | dos as u32;
+    }
+    pub fn dos_attributes(&self) -> u8 {
+        self.external_file_attributes as u8
+    }
+    /// 只修改低8位，Unix权限保持不变
+    pub fn set_dos_attributes(&mut self, attributes: u8) {
+        self.external_file_attributes = (self.external_file_attributes & !0xFF) | attributes as u32;
+    }
+    /// 设置头部的DOS时间和扩展时间戳，中央目录的扩展时间戳只包含修改时间。
+    /// NTFS扩展必须包含三个时间，只在访问和创建时间都存在时写入
+    pub fn set_times(&mut self, times: EntryTimes, zone: TimeZone) {
+        let dos = DosDateTime::from_system_time(times.modified, zone);
+        self.last_modification_time = dos.time;
+        self.last_modification_date = dos.date;
+        self.file.last_modification_time = dos.time;
+        self.file.last_modification_date = dos.date;
+        let ntfs = match (times.accessed, times.created) {
+            (Some(accessed), Some(created)) => Some(Extra::NTFS {
+                mtime: to_ntfs(times.modified),
+                atime: to_ntfs(accessed),
+                ctime: to_ntfs(created),
+            }),
+            _ => None,
+        };
+        let mtime = Some(to_unix_timestamp(times.modified));
+        let local = Extra::UnixExtendedTimestamp {
+            mtime,
+            atime: times.accessed.map(to_unix_timestamp),
+            ctime: times.created.map(to_unix_timestamp),
+        };
+        let central = Extra::UnixExtendedTimestamp {
+            mtime,
+            atime: None,
+            ctime: None,
+        };
+        for (extra_fields, timestamp) in [
+            (&mut self.extra_fields, central),
+            (&mut self.file.extra_fields, local),
+        ] {
+            //旧的Unix时间扩展和原有的NTFS扩展不再准确
+            extra_fields.0.retain(|extra| {
+                !matches!(
+                    extra,
+                    Extra::UnixOldExtendedTimestamp { .. } | Extra::NTFS { .. }
+                )
+            });
+            if let Some(ntfs) = &ntfs {
+                extra_fields.0.push(ntfs.clone());
+            }
+            extra_fields.replace(timestamp);
+        }
+    }
+    /// 优先取NTFS扩展和扩展时间戳，没有时按zone换算头部的DOS时间
+    pub fn modified(&self, zone: TimeZone) -> Option<SystemTime> {
+        self.extra_times()[0].or_else(|| {
+            DosDateTime {
+                date: self.last_modification_date,
+                time: self.last_modification_time,
+            }
+            .to_system_time(zone)
+        })
+    }
+    pub fn accessed(&self) -> Option<SystemTime> {
+        self.extra_times()[1]
+    }
+    pub fn created(&self) -> Option<SystemTime> {
+        self.extra_times()[2]
+    }
+    // 修改、访问、创建时间，本地头的扩展时间戳比中央目录完整
+    fn extra_times(&self) -> [Option<SystemTime>; 3] {
+        let lists = [&self.file.extra_fields, &self.extra_fields];
+        let ntfs = lists
+            .into_iter()
+            .flat_map(|list| &list.0)
+            .find_map(|extra| match extra {
+                Extra::NTFS {
+                    mtime,
+                    atime,
+                    ctime,
+                } if *mtime != 0 => Some([mtime, atime, ctime]),
+                _ => None,
+            });
+        if let Some(times) = ntfs {
+            return times.map(|time| (*time != 0).then(|| from_ntfs(*time)));
+        }
+        lists
+            .into_iter()
+            .flat_map(|list| &list.0)
+            .find_map(|extra| match extra {
+                Extra::UnixExtendedTimestamp {
+                    mtime,
+                    atime,
+                    ctime,
+                } if mtime.is_some() => Some([mtime, atime, ctime]),
+                _ => None,
+            })
+            .map(|times| times.map(|time| time.map(from_unix_timestamp)))
+            .unwrap_or_default()
+    }
+    /// 名称或注释含非ASCII的UTF-8字符时需要设置bit 11
+    pub fn utf8_flag(&self) -> u16 {
+        utf8_flag(self.file_name.bytes()) | utf8_flag(&self.file_comment)
+    }
+    /// flags bit 0，AES和传统加密都会设置
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+    /// 设置打包时使用的压缩算法，只对未压缩的数据生效
+    pub fn set_compression_method(&mut self, compression_method: CompressionMethod) {
+        let extract_zip_spec = compression_method.extract_version();
+        self.extract_zip_spec = self.extract_zip_spec.max(extract_zip_spec);
+        self.file.extract_zip_spec = self.file.extract_zip_spec.max(extract_zip_spec);
+        self.compression_method = compression_method.clone();
+        self.file.compression_method = compression_method;
+    }
+    /// 设置打包为Zstd时的压缩级别，超出1-22时返回错误
+    pub fn set_zstd_level(&mut self, level: i32) -> BinResult<()> {
+        if !(1..=22).contains(&level) {
+            return Err(Error::AssertFail(format!(
+                "zstd level {} out of range 1-22",
+                level
+            )));
+        }
+        self.zstd_level = Some(level);
+        Ok(())
+    }
+    /// 自定义扩展，优先取中央目录，其次取本地头
+    pub fn extra<E: ExtraField>(&self) -> Option<&E> {
+        self.extra_fields
+            .extra()
+            .or_else(|| self.file.extra_fields.extra())
+    }
+    /// 同时写入中央目录和本地头
+    pub fn set_extra<E: ExtraField + Clone>(&mut self, value: E) {
+        self.file.extra_fields.set_extra(value.clone());
+        self.extra_fields.set_extra(value);
+    }
+    pub fn remove_extra<E: ExtraField>(&mut self) {
+        self.extra_fields.remove_extra::<E>();
+        self.file.extra_fields.remove_extra::<E>();
+    }
+    pub fn resolve_extras(&mut self, registry: &ExtraRegistry) {
+        self.extra_fields.resolve(registry);
+        self.file.extra_fields.resolve(registry);
+    }
     //     pub fn is_file(name: &Name) -> bool {
     //         !name.inner.ends_with(&[b'/'])
     //     }
@@ -574,7 +821,7 @@ where
         if *model == ZipModel::Bin {
             return reader.read_type(endian).await;
         }
-        Ok(*compression_method == CompressionMethod::Deflate)
+        Ok(*compression_method != CompressionMethod::Store)
     }
 }
 // #[binrw::parser(reader)]
@@ -584,8 +831,8 @@ pub fn data_parse<T, R: Read + Seek + Send>(
     config: &T::Config,
     is_file: bool,
     data_position: u64,
-    compressed_size: u32,
-    uncompressed_size: u32,
+    compressed_size: u64,
+    uncompressed_size: u64,
 ) -> impl Future<Output = BinResult<T>> + Send
 where
     T: Read + Write + Seek + Send + StreamDefault,
@@ -600,10 +847,10 @@ where
         if *model == ZipModel::Parse {
             reader.set_position(data_position).await?;
         }
-        let mut take_reader = reader.take(compressed_size as u64);
+        let mut take_reader = reader.take(compressed_size);
         let mut config = config.clone();
-        config.compress_size_mut(compressed_size as u64);
-        config.un_compress_size_mut(uncompressed_size as u64);
+        config.compress_size_mut(compressed_size);
+        config.un_compress_size_mut(uncompressed_size);
         let mut data = T::from_config(&config).await?;
         binrw::io::copy(&mut take_reader, &mut data).await?;
         data.seek_start().await?;
@@ -625,12 +872,14 @@ fn zip_file_writer<W: Write + Seek + Send>(
     endian: Endian,
     value: &ZipFile,
     model: &ZipModel,
-    uncompressed_size: u32,
+    uncompressed_size: u64,
 ) -> impl Future<Output = BinResult<()>> + Send {
     async move {
         if *model == ZipModel::Bin {
+            let zip64 =
+                value.compressed_size >= ZIP64_LIMIT || value.uncompressed_size >= ZIP64_LIMIT;
             writer
-                .write_type_args(value, endian, (model, uncompressed_size))
+                .write_type_args(value, endian, (model, uncompressed_size, zip64))
                 .await?;
         }
         Ok(())
@@ -641,18 +890,17 @@ fn zip_file_parse<R: Read + Seek + Send>(
     reader: &mut R,
     endian: Endian,
     model: &ZipModel,
-    offset_of_local_file_header: u32,
-    uncompressed_size: u32,
+    offset_of_local_file_header: u64,
+    uncompressed_size: u64,
+    options: &ParseOptions,
 ) -> impl Future<Output = BinResult<ZipFile>> + Send {
     async move {
         let pos = reader.position().await?;
         if *model == ZipModel::Parse {
-            reader
-                .set_position(offset_of_local_file_header as u64)
-                .await?;
+            reader.set_position(offset_of_local_file_header).await?;
         }
         let value = reader
-            .read_type_args(endian, (model, uncompressed_size))
+            .read_type_args(endian, (model, uncompressed_size, options))
             .await?;
         if *model == ZipModel::Parse {
             reader.set_position(pos).await?;
@@ -669,6 +917,220 @@ where
     pub fn compressed(&self) -> bool {
         self.compressed
     }
+    /// 读取本地头和数据，有数据描述符时以其中的值为准
+    fn load_local<'a, R>(
+        &'a mut self,
+        reader: &'a mut R,
+        endian: Endian,
+        model: &'a ZipModel,
+        config: &'a T::Config,
+        options: &'a ParseOptions,
+        read_bytes: &'a mut ReadBytesCallback<'_>,
+    ) -> impl Future<Output = BinResult<()>> + Send + 'a
+    where
+        R: Read + Seek + Send,
+    {
+        async move {
+            let pos = reader.position().await?;
+            let mut file: ZipFile = zip_file_parse(
+                reader,
+                endian,
+                model,
+                self.offset_of_local_file_header,
+                self.uncompressed_size,
+                options,
+            )
+            .await?;
+            //本地头可能没有Unicode扩展，以中央目录的名称为准
+            file.file_name = self.file_name.clone();
+            read_bytes(reader.position().await?.saturating_sub(pos)).await?;
+            // reader.seek(SeekFrom::Start(pos))?;
+            let data = if self.is_dir() {
+                T::from_config(config).await?
+            } else {
+                if *model == ZipModel::Bin {
+                    let length: u64 = reader.read_type(endian).await?;
+                    let mut data = reader.take(length);
+                    let mut writer = T::from_config(config).await?;
+                    binrw::io::copy(&mut data, &mut writer).await?;
+                    writer.seek_start().await?;
+                    read_bytes(length).await?;
+                    writer
+                } else {
+                    let pos = reader.position().await?;
+                    if *model == ZipModel::Parse {
+                        let length = reader.length().await?;
+                        if file
+                            .data_position
+                            .checked_add(self.compressed_size)
+                            .is_none_or(|end| end > length)
+                        {
+                            return Err(ZipError::Corrupt {
+                                name: Some(
+                                    String::from_utf8_lossy(&self.file_name.inner).to_string(),
+                                ),
+                                offset: Some(self.offset_of_local_file_header),
+                                message: "data runs past end of file".to_string(),
+                            }
+                            .into());
+                        }
+                        reader.set_position(file.data_position).await?;
+                    }
+                    let config_pos = reader.position().await?;
+                    let mut config = config.clone();
+                    config.compress_size_mut(self.compressed_size);
+                    config.un_compress_size_mut(self.uncompressed_size);
+                    let (mut data, need_copy) =
+                        T::from_link_config(config_pos, self.compressed_size, &config).await?;
+                    if need_copy {
+                        let mut take_reader = reader.take(self.compressed_size);
+                        let mut buffer = vec![0u8; 1024 * 8];
+                        loop {
+                            let len = take_reader.read(&mut buffer).await?;
+                            if len == 0 {
+                                break;
+                            }
+                            data.write_all(&buffer[..len]).await?;
+                            read_bytes(len as u64).await?;
+                        }
+                    } else {
+                        reader.seek_relative(self.compressed_size as i64).await?;
+                        read_bytes(self.compressed_size).await?;
+                    }
+                    data.seek_start().await?;
+                    //以中央目录的值为准，数据描述符单独保存，由verify比较
+                    file.data_descriptor = if file.flags & 0x0008 != 0 {
+                        //TODO数据是流式的
+                        Some(reader.read_le_args(file.extra_fields.has_zip64()).await?)
+                    } else {
+                        None
+                    };
+                    if *model == ZipModel::Parse {
+                        reader.set_position(pos).await?;
+                    }
+                    data
+                }
+            };
+            self.file = file;
+            self.data = Some(data);
+            self.loaded = true;
+            Ok(())
+        }
+    }
+    /// 只读中央目录解析时，本地头和数据在第一次使用前从source读取，source为整个压缩包
+    pub fn load(
+        &mut self,
+        source: &mut T,
+        options: &ParseOptions,
+    ) -> impl Future<Output = BinResult<()>> + Send {
+        async move {
+            if self.loaded {
+                return Ok(());
+            }
+            let config = source.config().clone();
+            let position = source.position().await?;
+            let mut reader = BufReader::with_capacity(8 * 1024, &mut *source);
+            let result = self
+                .load_local(
+                    &mut reader,
+                    Endian::Little,
+                    &ZipModel::Parse,
+                    &config,
+                    options,
+                    &mut |_bytes| Box::pin(async { Ok(()) }),
+                )
+                .await;
+            source.set_position(position).await?;
+            result.map_err(|e| self.entry_error(e))
+        }
+    }
+    /// 错误转换为ZipError并补充该条目的名称和本地头偏移
+    pub fn zip_error(&self, error: Error) -> ZipError {
+        let name = String::from_utf8_lossy(&self.file_name.inner);
+        ZipError::from(error).with_entry(&name, self.offset_of_local_file_header)
+    }
+    // 所有错误都转换为带条目名称和偏移的ZipError，已包含条目信息的保持不变
+    pub(crate) fn entry_error(&self, error: Error) -> Error {
+        self.zip_error(error).into()
+    }
+    /// 不修改条目，把数据解压到空输出，返回实际的CRC-32和长度
+    pub fn inflate_checksum(&self) -> impl Future<Output = BinResult<(u32, u64)>> + Send {
+        self.inflate_checksum_with_password(self.password.as_deref())
+    }
+    /// 同inflate_checksum，加密条目使用指定的密码
+    pub(crate) fn inflate_checksum_with_password<'a>(
+        &'a self,
+        password: Option<&'a [u8]>,
+    ) -> impl Future<Output = BinResult<(u32, u64)>> + Send {
+        async move {
+            let result = self.inflate_to_null(password).await;
+            result.map_err(|e| self.entry_error(e))
+        }
+    }
+    fn inflate_to_null<'a>(
+        &'a self,
+        password: Option<&'a [u8]>,
+    ) -> impl Future<Output = BinResult<(u32, u64)>> + Send {
+        async move {
+            let Some(data) = &self.data else {
+                return Err(Error::AssertFail("directory data is none".to_string()));
+            };
+            let mut sink = NullWriter;
+            let mut crc32_writer = Crc32Writer::new(&mut sink, true);
+            if self.is_encrypted() && !self.is_dir() {
+                //边读边解密，不复制数据
+                let mut reader = self
+                    .open_reader_with(password, self.limits.clone(), false)
+                    .await?;
+                binrw::io::copy(&mut reader, &mut crc32_writer)
+                    .await
+                    .map_err(|e| map_limit_error(map_password_error(e)))?;
+            } else {
+                let mut data = data.link().await?;
+                data.seek_start().await?;
+                let name = String::from_utf8_lossy(&self.file_name.inner);
+                let length = data.length().await?;
+                let mut limit_writer =
+                    LimitWriter::new(&mut crc32_writer, self.limits.as_ref(), &name, length);
+                if self.compressed() {
+                    codec::decompress(
+                        &self.compression_method,
+                        self.flags,
+                        self.uncompressed_size,
+                        &mut data,
+                        &mut limit_writer,
+                    )
+                    .await
+                    .map_err(map_limit_error)?;
+                } else {
+                    binrw::io::copy(&mut data, &mut limit_writer)
+                        .await
+                        .map_err(|e| map_limit_error(e.into()))?;
+                }
+            }
+            Ok(crc32_writer.finish().unwrap_or_default())
+        }
+    }
+    /// WinZip AE-2条目，解密时扩展字段被移除，需要在解密前判断
+    pub(crate) fn is_ae2(&self) -> bool {
+        self.extra_fields.is_ae2() || self.file.extra_fields.is_ae2()
+    }
+    // AE-2条目通过认证码校验后记录实际的CRC-32，之后按普通条目校验
+    fn check_checksum(&mut self, computed: Option<(u32, u64)>, ae2: bool) -> BinResult<()> {
+        let Some(actual) = computed else {
+            return Ok(());
+        };
+        let name = String::from_utf8_lossy(&self.file_name.inner);
+        let expected = (self.crc_32_uncompressed_data, self.uncompressed_size);
+        if let Some(error) = checksum_error(&name, expected, actual, ae2) {
+            return Err(error.into_error());
+        }
+        if ae2 {
+            self.crc_32_uncompressed_data = actual.0;
+            self.file.crc_32_uncompressed_data = actual.0;
+        }
+        Ok(())
+    }
     pub fn decompressed_with_writer<'a, W>(
         &mut self,
         writer: &'a mut W,
@@ -677,6 +1139,36 @@ where
         W: Write + Seek + Send,
     {
         async move {
+            let password = self.password.clone();
+            self.decompressed_with_writer_password(writer, password.as_deref())
+                .await
+        }
+    }
+    /// 同decompressed_with_writer，加密条目使用指定的密码
+    pub(crate) fn decompressed_with_writer_password<'a, W>(
+        &mut self,
+        writer: &'a mut W,
+        password: Option<&'a [u8]>,
+    ) -> impl Future<Output = BinResult<()>> + Send
+    where
+        W: Write + Seek + Send,
+    {
+        async move {
+            let result = self.decompress_to_writer(writer, password).await;
+            result.map_err(|e| self.entry_error(e))
+        }
+    }
+    fn decompress_to_writer<'a, W>(
+        &mut self,
+        writer: &'a mut W,
+        password: Option<&'a [u8]>,
+    ) -> impl Future<Output = BinResult<()>> + Send
+    where
+        W: Write + Seek + Send,
+    {
+        async move {
+            let ae2 = self.is_ae2();
+            self.decrypt_with_password(password).await?;
             if self.compressed() {
                 // let (new_data, sha) = {
                 if let Some(data) = &mut self.data {
@@ -686,9 +1178,20 @@ where
                     config.compress_size_mut(length);
                     // let new_data = T::from_config(&config).await?;
                     // let mut hash_writer = HashWriter::new(new_data);
-                    decompress_stream(&mut *data, writer)
-                        .await
-                        .map_err(|e| Error::Err(Box::new(e)))?;
+                    let name = String::from_utf8_lossy(&self.file_name.inner);
+                    let mut limit_writer =
+                        LimitWriter::new(writer, self.limits.as_ref(), &name, length);
+                    let mut crc32_writer = Crc32Writer::new(&mut limit_writer, self.verify_crc32);
+                    codec::decompress(
+                        &self.compression_method,
+                        self.flags,
+                        self.uncompressed_size,
+                        &mut *data,
+                        &mut crc32_writer,
+                    )
+                    .await
+                    .map_err(map_limit_error)?;
+                    self.check_checksum(crc32_writer.finish(), ae2)?;
                     // let value = hash_writer.hash();
                     // let mut new_data = hash_writer.into_inner();
                     writer.seek_start().await?;
@@ -703,7 +1206,15 @@ where
             } else {
                 if let Some(data) = &mut self.data {
                     data.seek_start().await?;
-                    binrw::io::copy(data, writer).await?;
+                    let name = String::from_utf8_lossy(&self.file_name.inner);
+                    let length = data.length().await?;
+                    let mut limit_writer =
+                        LimitWriter::new(writer, self.limits.as_ref(), &name, length);
+                    let mut crc32_writer = Crc32Writer::new(&mut limit_writer, self.verify_crc32);
+                    binrw::io::copy(data, &mut crc32_writer)
+                        .await
+                        .map_err(|e| map_limit_error(e.into()))?;
+                    self.check_checksum(crc32_writer.finish(), ae2)?;
                 }
             }
             Ok(())
@@ -717,6 +1228,36 @@ where
         C: BytesCallback + Send,
     {
         async move {
+            let password = self.password.clone();
+            self.decompressed_with_callback_password(callback, password.as_deref())
+                .await
+        }
+    }
+    /// 同decompressed_with_callback，加密条目使用指定的密码
+    pub(crate) fn decompressed_with_callback_password<'a, C>(
+        &'a mut self,
+        callback: &'a mut C,
+        password: Option<&'a [u8]>,
+    ) -> impl Future<Output = BinResult<()>> + Send
+    where
+        C: BytesCallback + Send,
+    {
+        async move {
+            let result = self.decompress_to_callback(callback, password).await;
+            result.map_err(|e| self.entry_error(e))
+        }
+    }
+    fn decompress_to_callback<'a, C>(
+        &'a mut self,
+        callback: &'a mut C,
+        password: Option<&'a [u8]>,
+    ) -> impl Future<Output = BinResult<()>> + Send
+    where
+        C: BytesCallback + Send,
+    {
+        async move {
+            let ae2 = self.is_ae2();
+            self.decrypt_with_password(password).await?;
             if self.compressed() {
                 let (new_data, sha) = {
                     if let Some(data) = &mut self.data {
@@ -724,14 +1265,27 @@ where
                         let mut config = data.config().clone();
                         let length = data.length().await?;
                         config.compress_size_mut(length);
-                        let new_data = T::from_config(&config).await?;
+                        config.un_compress_size_mut(self.uncompressed_size);
+                        let new_data = TempData::<T>::new(&config).await?;
                         let mut hash_writer = HashWriter::new(new_data);
                         let mut reader = ReadCallback::new(data, callback);
-                        decompress_stream(&mut reader, &mut hash_writer)
-                            .await
-                            .map_err(|e| Error::Err(Box::new(e)))?;
+                        let name = String::from_utf8_lossy(&self.file_name.inner);
+                        let mut limit_writer =
+                            LimitWriter::new(&mut hash_writer, self.limits.as_ref(), &name, length);
+                        let mut crc32_writer =
+                            Crc32Writer::new(&mut limit_writer, self.verify_crc32);
+                        codec::decompress(
+                            &self.compression_method,
+                            self.flags,
+                            self.uncompressed_size,
+                            &mut reader,
+                            &mut crc32_writer,
+                        )
+                        .await
+                        .map_err(map_limit_error)?;
+                        self.check_checksum(crc32_writer.finish(), ae2)?;
                         let value = hash_writer.hash();
-                        let mut new_data = hash_writer.into_inner();
+                        let mut new_data = hash_writer.into_inner().into_inner();
                         new_data.seek_start().await?;
                         (new_data, value)
                     } else {
@@ -751,6 +1305,14 @@ where
     }
     pub fn decompressed<'a>(&mut self) -> impl Future<Output = BinResult<()>> + Send {
         async move {
+            let result = self.decompress_to_data().await;
+            result.map_err(|e| self.entry_error(e))
+        }
+    }
+    fn decompress_to_data(&mut self) -> impl Future<Output = BinResult<()>> + Send {
+        async move {
+            let ae2 = self.is_ae2();
+            self.decrypt().await?;
             if self.compressed() {
                 let (new_data, sha) = {
                     if let Some(data) = &mut self.data {
@@ -758,13 +1320,26 @@ where
                         let mut config = data.config().clone();
                         let length = data.length().await?;
                         config.compress_size_mut(length);
-                        let new_data = T::from_config(&config).await?;
+                        config.un_compress_size_mut(self.uncompressed_size);
+                        let new_data = TempData::<T>::new(&config).await?;
                         let mut hash_writer = HashWriter::new(new_data);
-                        decompress_stream(&mut *data, &mut hash_writer)
-                            .await
-                            .map_err(|e| Error::Err(Box::new(e)))?;
+                        let name = String::from_utf8_lossy(&self.file_name.inner);
+                        let mut limit_writer =
+                            LimitWriter::new(&mut hash_writer, self.limits.as_ref(), &name, length);
+                        let mut crc32_writer =
+                            Crc32Writer::new(&mut limit_writer, self.verify_crc32);
+                        codec::decompress(
+                            &self.compression_method,
+                            self.flags,
+                            self.uncompressed_size,
+                            &mut *data,
+                            &mut crc32_writer,
+                        )
+                        .await
+                        .map_err(map_limit_error)?;
+                        self.check_checksum(crc32_writer.finish(), ae2)?;
                         let value = hash_writer.hash();
-                        let mut new_data = hash_writer.into_inner();
+                        let mut new_data = hash_writer.into_inner().into_inner();
                         new_data.seek_start().await?;
                         (new_data, value)
                     } else {
@@ -821,19 +1396,19 @@ where
         compression_level: CompressionLevel,
     ) -> impl Future<Output = BinResult<()>> + Send {
         async move {
-            if !self.compressed && self.compression_method == CompressionMethod::Deflate {
+            if !self.compressed && self.compression_method != CompressionMethod::Store {
                 let mut config = config.clone();
-                config.compress_size_mut(self.compressed_size as u64);
+                config.compress_size_mut(self.compressed_size);
                 let compress_data = {
                     if let Some(mut data) = self.data.take() {
                         data.seek_start().await?;
                         let uncompressed_size = data.length().await?;
                         self.crc_32_uncompressed_data = 0; //crc32 设置为0也能安装，网页可以忽略计算加快速度
                         self.file.crc_32_uncompressed_data = 0;
-                        self.uncompressed_size = uncompressed_size as u32;
-                        self.file.uncompressed_size = uncompressed_size as u32;
+                        self.uncompressed_size = uncompressed_size;
+                        self.file.uncompressed_size = uncompressed_size;
                         let mut config = config.clone();
-                        config.compress_size_mut(self.compressed_size as u64);
+                        config.compress_size_mut(self.compressed_size);
                         let mut compress_data = T::from_config(&config).await?;
                         // let mut compress_data =
                         // BufWriter::with_capacity(3 * 32 * 1024, compress_data);
@@ -845,13 +1420,14 @@ where
                         // let mut crc32_reader =
                         // BufReader::with_capacity(3 * 32 * 1024, crc32_reader);
                         if uncompressed_size > 0 {
-                            miniz_oxide::deflate::stream::compress_stream_callback(
+                            codec::compress(
+                                &self.compression_method,
+                                compression_level,
+                                self.zstd_level,
                                 &mut crc32_reader,
                                 &mut compress_data,
-                                compression_level,
                             )
-                            .await
-                            .map_err(|e| Error::Err(Box::new(e)))?;
+                            .await?;
                         }
                         // crc32_reader.rewind_position().await?;
                         // let crc32_reader = crc32_reader.into_inner();
@@ -859,7 +1435,7 @@ where
                         // let mut compress_data = compress_data.into_inner();
                         self.crc_32_uncompressed_data = crc32_reader.crc32();
                         self.file.crc_32_uncompressed_data = self.crc_32_uncompressed_data;
-                        self.compressed_size = compress_data.length().await? as u32;
+                        self.compressed_size = compress_data.length().await?;
                         self.file.compressed_size = self.compressed_size;
                         compress_data.seek_start().await?;
                         Some(compress_data)
@@ -881,39 +1457,40 @@ where
         crc32_computer: bool,
         compression_level: CompressionLevel,
         writer: &'a mut W,
-    ) -> impl Future<Output = BinResult<Option<(u32, u32)>>> + Send
+    ) -> impl Future<Output = BinResult<Option<(u32, u64)>>> + Send
     where
         W: Write + Seek + Send,
     {
         async move {
-            if !self.compressed && self.compression_method == CompressionMethod::Deflate {
+            if !self.compressed && self.compression_method != CompressionMethod::Store {
                 let mut config = config.clone();
-                config.compress_size_mut(self.compressed_size as u64);
+                config.compress_size_mut(self.compressed_size);
                 let result = if let Some(mut data) = self.data.take() {
                     data.seek_start().await?;
                     let uncompressed_size = data.length().await?;
                     self.crc_32_uncompressed_data = 0;
                     self.file.crc_32_uncompressed_data = 0;
-                    self.uncompressed_size = uncompressed_size as u32;
-                    self.file.uncompressed_size = uncompressed_size as u32;
+                    self.uncompressed_size = uncompressed_size;
+                    self.file.uncompressed_size = uncompressed_size;
                     let mut config = config.clone();
-                    config.compress_size_mut(self.compressed_size as u64);
+                    config.compress_size_mut(self.compressed_size);
                     let mut crc32_reader = Crc32Reader::new(data);
                     if crc32_computer {
                         crc32_reader.init_crc32();
                     }
                     let pos = writer.position().await?;
                     if uncompressed_size > 0 {
-                        miniz_oxide::deflate::stream::compress_stream_callback(
+                        codec::compress(
+                            &self.compression_method,
+                            compression_level,
+                            self.zstd_level,
                             &mut crc32_reader,
                             writer,
-                            compression_level,
                         )
-                        .await
-                        .map_err(|e| Error::Err(Box::new(e)))?;
+                        .await?;
                     }
                     let compress_size = writer.position().await? - pos;
-                    Some((crc32_reader.crc32(), compress_size as u32))
+                    Some((crc32_reader.crc32(), compress_size))
                 } else {
                     self.crc_32_uncompressed_data = 0;
                     self.file.crc_32_uncompressed_data = 0;
@@ -933,24 +1510,24 @@ where
         compression_level: CompressionLevel,
         writer: &'a mut W,
         callback: &'a mut C,
-    ) -> impl Future<Output = BinResult<Option<(u32, u32)>>> + Send
+    ) -> impl Future<Output = BinResult<Option<(u32, u64)>>> + Send
     where
         W: Write + Seek + Send,
         C: BytesCallback + Send,
     {
         async move {
-            if !self.compressed && self.compression_method == CompressionMethod::Deflate {
+            if !self.compressed && self.compression_method != CompressionMethod::Store {
                 let mut config = config.clone();
-                config.compress_size_mut(self.compressed_size as u64);
+                config.compress_size_mut(self.compressed_size);
                 let result = if let Some(mut data) = self.data.take() {
                     data.seek_start().await?;
                     let uncompressed_size = data.length().await?;
                     self.crc_32_uncompressed_data = 0;
                     self.file.crc_32_uncompressed_data = 0;
-                    self.uncompressed_size = uncompressed_size as u32;
-                    self.file.uncompressed_size = uncompressed_size as u32;
+                    self.uncompressed_size = uncompressed_size;
+                    self.file.uncompressed_size = uncompressed_size;
                     let mut config = config.clone();
-                    config.compress_size_mut(self.compressed_size as u64);
+                    config.compress_size_mut(self.compressed_size);
                     let mut crc32_reader = Crc32Reader::new(data);
                     if crc32_computer {
                         crc32_reader.init_crc32();
@@ -958,17 +1535,18 @@ where
                     let mut crc32_reader = ReadCallback::new(crc32_reader, callback);
                     let pos = writer.position().await?;
                     if uncompressed_size > 0 {
-                        miniz_oxide::deflate::stream::compress_stream_callback(
+                        codec::compress(
+                            &self.compression_method,
+                            compression_level,
+                            self.zstd_level,
                             &mut crc32_reader,
                             writer,
-                            compression_level,
                         )
-                        .await
-                        .map_err(|e| Error::Err(Box::new(e)))?;
+                        .await?;
                     }
                     let crc32_reader = crc32_reader.into_inner();
                     let compress_size = writer.position().await? - pos;
-                    Some((crc32_reader.crc32(), compress_size as u32))
+                    Some((crc32_reader.crc32(), compress_size))
                 } else {
                     self.crc_32_uncompressed_data = 0;
                     self.file.crc_32_uncompressed_data = 0;
@@ -981,9 +1559,304 @@ where
             }
         }
     }
+    // 本地头在压缩前写出，压缩后可能略大于原始大小，预留余量
+    fn local_zip64(&self, force_zip64: bool) -> bool {
+        let size = self.uncompressed_size.max(self.compressed_size);
+        force_zip64 || size >= ZIP64_LIMIT - (ZIP64_LIMIT >> 8)
+    }
+    /// 打包时使用的密码和加密方式，条目单独设置的优先于FastZip的设置。
+    /// 目录和已加密的数据原样拷贝，不重复加密
+    pub(crate) fn package_encryption(
+        &self,
+        password: Option<&[u8]>,
+        encryption: Encryption,
+    ) -> Option<(Vec<u8>, Encryption)> {
+        if self.is_dir() || self.is_encrypted() {
+            return None;
+        }
+        let password = self.password.as_deref().or(password)?;
+        Some((password.to_vec(), self.encryption.unwrap_or(encryption)))
+    }
+    /// 写出本地头、数据和数据描述符，encryption由package_encryption得到
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn write_local_entry<'a, W, C>(
+        &'a mut self,
+        config: &'a T::Config,
+        crc32_computer: bool,
+        compression_level: CompressionLevel,
+        force_zip64: bool,
+        encryption: Option<(Vec<u8>, Encryption)>,
+        writer: &'a mut W,
+        callback: &'a mut C,
+    ) -> impl Future<Output = BinResult<()>> + Send
+    where
+        W: Write + Seek + Send,
+        C: BytesCallback + Send,
+    {
+        async move {
+            let is_dir = self.is_dir();
+            let name = String::from_utf8_lossy(&self.file_name.inner).to_string();
+            if !is_dir
+                && (self.compression_method != CompressionMethod::Store || encryption.is_some())
+            {
+                //已压缩的数据原样拷贝，保留加密和算法相关的标志位(bit 0,1,2)
+                let method_flags = if self.compressed {
+                    self.flags & 0x07
+                } else {
+                    codec::general_flags(&self.compression_method)
+                };
+                self.flags = 0x08 | method_flags;
+                if encryption.is_some() {
+                    self.flags |= 0x01;
+                }
+                self.file.flags = self.flags;
+            }
+            if let Some((_, Encryption::Aes(strength))) = &encryption {
+                let actual = if self.uncompressed_size == 0 {
+                    CompressionMethod::Store
+                } else {
+                    self.compression_method.clone()
+                };
+                let extra = aes_extra(*strength, actual);
+                self.file.extra_fields.set_aes(extra.clone());
+                self.extra_fields.set_aes(extra);
+                self.file.compression_method = CompressionMethod::AES;
+                self.file.crc_32_uncompressed_data = 0;
+                self.file.extract_zip_spec = self.file.extract_zip_spec.max(AES_VERSION);
+                self.extract_zip_spec = self.extract_zip_spec.max(AES_VERSION);
+            }
+            //本地头和中央目录的bit 11保持一致
+            self.file.flags = (self.file.flags & !UTF8_FLAG) | self.utf8_flag();
+            let zip64 = self.local_zip64(force_zip64);
+            let mut local_header_writer = Cursor::new(vec![]);
+            local_header_writer
+                .write_le_args(
+                    &self.file,
+                    (&ZipModel::Parse, self.uncompressed_size, zip64),
+                )
+                .await?;
+            writer.write_all(local_header_writer.get_ref()).await?;
+
+            if !is_dir {
+                if let Some((password, encryption)) = &encryption {
+                    //有数据描述符，传统加密头部用修改时间的高8位校验
+                    let check = (self.file.last_modification_time >> 8) as u8;
+                    let zip_crypto = *encryption == Encryption::ZipCrypto;
+                    let data_start = writer.position().await?;
+                    let mut encrypt_writer =
+                        EncryptWriter::new(writer, password, *encryption, check, &name).await?;
+                    //传统加密依赖CRC校验密码和数据，必须计算
+                    let result = self
+                        .write_data(
+                            config,
+                            crc32_computer || zip_crypto,
+                            compression_level,
+                            &mut encrypt_writer,
+                            callback,
+                        )
+                        .await?;
+                    encrypt_writer.finish().await?;
+                    let compressed_size = writer.position().await? - data_start;
+                    //AE-2不写CRC，由认证码校验数据
+                    let crc32 = match result {
+                        _ if !zip_crypto => 0,
+                        Some((crc32, _)) => crc32,
+                        None => self.crc_32_uncompressed_data,
+                    };
+                    if !zip_crypto {
+                        self.compression_method = CompressionMethod::AES;
+                    }
+                    self.compressed = true;
+                    self.compressed_size = compressed_size;
+                    self.crc_32_uncompressed_data = crc32;
+                    self.file.crc_32_uncompressed_data = 0;
+                    self.file.data_descriptor = Some(DataDescriptor {
+                        crc32,
+                        compressed_size,
+                        uncompressed_size: self.uncompressed_size,
+                        zip64,
+                    });
+                } else if let Some((crc32, compressed_size)) = self
+                    .write_data(config, crc32_computer, compression_level, writer, callback)
+                    .await?
+                {
+                    self.compressed_size = compressed_size;
+                    self.crc_32_uncompressed_data = crc32;
+                    self.file.data_descriptor = Some(DataDescriptor {
+                        crc32,
+                        compressed_size,
+                        uncompressed_size: self.uncompressed_size,
+                        zip64,
+                    });
+                } else if self.data.is_some() && self.compression_method != CompressionMethod::Store
+                {
+                    //原样写出的数据，描述符与写出的中央目录一致
+                    self.file.data_descriptor = Some(DataDescriptor {
+                        crc32: self.crc_32_uncompressed_data,
+                        compressed_size: self.compressed_size,
+                        uncompressed_size: self.uncompressed_size,
+                        zip64,
+                    });
+                    self.file.crc_32_uncompressed_data = 0;
+                    self.file.compressed_size = 0;
+                }
+            }
+
+            if let Some(data_descriptor) = &mut self.file.data_descriptor {
+                if !zip64
+                    && (data_descriptor.compressed_size >= ZIP64_LIMIT
+                        || data_descriptor.uncompressed_size >= ZIP64_LIMIT)
+                {
+                    return Err(Error::AssertFail(format!(
+                        "{} exceeds 4GiB after compression but local header is not zip64",
+                        name
+                    )));
+                }
+                data_descriptor.zip64 = zip64;
+                let mut dd_writer = Cursor::new(vec![]);
+                dd_writer.write_le(data_descriptor).await?;
+                writer.write_all(dd_writer.get_ref()).await?;
+            }
+            Ok(())
+        }
+    }
+    // 压缩写出数据，返回(crc32, 压缩后大小)；已压缩或Store的数据原样拷贝并返回None
+    fn write_data<'a, W, C>(
+        &'a mut self,
+        config: &'a T::Config,
+        crc32_computer: bool,
+        compression_level: CompressionLevel,
+        writer: &'a mut W,
+        callback: &'a mut C,
+    ) -> impl Future<Output = BinResult<Option<(u32, u64)>>> + Send
+    where
+        W: Write + Seek + Send,
+        C: BytesCallback + Send,
+    {
+        async move {
+            let result = self
+                .compress_to_writer_callback(
+                    config,
+                    crc32_computer,
+                    compression_level,
+                    writer,
+                    callback,
+                )
+                .await?;
+            if result.is_none()
+                && let Some(data) = &mut self.data
+            {
+                data.seek_start().await?;
+                binrw::io::copy(data, writer).await?;
+            }
+            Ok(result)
+        }
+    }
+    /// 解密加密条目(AES或传统加密)，成功后data为解密后的压缩数据并清除加密标志
+    pub fn decrypt(&mut self) -> impl Future<Output = BinResult<()>> + Send {
+        async move {
+            let password = self.password.clone();
+            self.decrypt_with_password(password.as_deref()).await
+        }
+    }
+    /// 同decrypt，使用指定的密码，失败时保留原始数据
+    pub(crate) fn decrypt_with_password<'a>(
+        &'a mut self,
+        password: Option<&'a [u8]>,
+    ) -> impl Future<Output = BinResult<()>> + Send {
+        async move {
+            if !self.is_encrypted() || self.is_dir() {
+                return Ok(());
+            }
+            let Some(mut data) = self.data.take() else {
+                return Err(Error::AssertFail("directory data is none".to_string()));
+            };
+            data.seek_start().await?;
+            let length = data.length().await?;
+            let result = self.open_decryptor(&mut data, length, password).await;
+            let (mut decryptor, actual) = match result {
+                Ok(value) => value,
+                Err(e) => {
+                    //保留原始数据，可以换密码重试
+                    self.data = Some(data);
+                    return Err(e);
+                }
+            };
+            let size = decryptor.remain();
+            let mut config = data.config().clone();
+            config.compress_size_mut(size);
+            let mut plain = TempData::<T>::new(&config).await?;
+            if let Err(e) = decryptor.copy(&mut data, &mut plain).await {
+                self.data = Some(data);
+                return Err(e);
+            }
+            let mut plain = plain.into_inner();
+            plain.seek_start().await?;
+            self.data = Some(plain);
+            self.compressed_size = size;
+            self.file.compressed_size = self.compressed_size;
+            self.flags &= !0x01;
+            self.file.flags &= !0x01;
+            if self.compression_method == CompressionMethod::AES {
+                self.extra_fields.remove_aes();
+                self.file.extra_fields.remove_aes();
+                self.compressed = actual != CompressionMethod::Store;
+                self.compression_method = actual.clone();
+                self.file.compression_method = actual;
+            }
+            Ok(())
+        }
+    }
+    /// 从reader开头读取加密头部并校验密码，返回解密状态和加密前的压缩算法
+    pub(crate) fn open_decryptor<'a, R>(
+        &'a self,
+        reader: &'a mut R,
+        length: u64,
+        password: Option<&'a [u8]>,
+    ) -> impl Future<Output = BinResult<(Decryptor, CompressionMethod)>> + Send
+    where
+        R: Read + Send,
+    {
+        async move {
+            let name = String::from_utf8_lossy(&self.file_name.inner).to_string();
+            //bit 6为强加密(SES)
+            if self.flags & 0x40 != 0 {
+                return Err(PasswordError::Unsupported(name).into_error());
+            }
+            let aes = if self.compression_method == CompressionMethod::AES {
+                match self.extra_fields.aes() {
+                    Some(aes) => Some(aes),
+                    None => return Err(PasswordError::Unsupported(name).into_error()),
+                }
+            } else {
+                None
+            };
+            let Some(password) = password else {
+                return Err(PasswordError::Missing(name).into_error());
+            };
+            match aes {
+                Some((strength, actual)) => {
+                    let decryptor =
+                        Decryptor::aes(reader, length, password, strength, &name).await?;
+                    Ok((decryptor, actual))
+                }
+                None => {
+                    //有数据描述符时CRC在数据之后，改用修改时间的高8位校验
+                    let check = if self.file.flags & 0x08 != 0 {
+                        (self.file.last_modification_time >> 8) as u8
+                    } else {
+                        (self.crc_32_uncompressed_data >> 24) as u8
+                    };
+                    let decryptor =
+                        Decryptor::zip_crypto(reader, length, password, check, &name).await?;
+                    Ok((decryptor, self.compression_method.clone()))
+                }
+            }
+        }
+    }
     pub fn put_data(&mut self, mut stream: T) -> impl Future<Output = BinResult<()>> + Send {
         async move {
-            let length = stream.length().await? as u32;
+            let length = stream.length().await?;
             self.sha_value = None;
             self.compressed_size = length;
             self.uncompressed_size = length;
@@ -991,6 +1864,7 @@ where
             self.file.uncompressed_size = self.uncompressed_size;
             self.compressed = false;
             self.data = Some(stream);
+            self.loaded = true;
             Ok(())
         }
     }
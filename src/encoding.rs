@@ -0,0 +1,95 @@
+use encoding_rs::{GBK, SHIFT_JIS};
+
+/// 未设置UTF-8标志时文件名的解码方式
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum NameEncoding {
+    /// 合法UTF-8直接使用，否则依次猜测GBK、Shift_JIS，都失败时按CP437。
+    /// 猜测可能误判，需要时通过ParseOptions::set_name_encoding开启
+    Auto,
+    /// 规范规定的默认编码
+    #[default]
+    Cp437,
+    Utf8,
+    Gbk,
+    ShiftJis,
+}
+
+impl NameEncoding {
+    pub fn decode(&self, raw: &[u8]) -> String {
+        match self {
+            Self::Auto => auto_decode(raw),
+            Self::Cp437 => cp437_decode(raw),
+            Self::Utf8 => String::from_utf8_lossy(raw).to_string(),
+            Self::Gbk => GBK.decode_without_bom_handling(raw).0.to_string(),
+            Self::ShiftJis => SHIFT_JIS.decode_without_bom_handling(raw).0.to_string(),
+        }
+    }
+}
+
+fn auto_decode(raw: &[u8]) -> String {
+    if let Ok(name) = std::str::from_utf8(raw) {
+        return name.to_string();
+    }
+    let shift_jis = SHIFT_JIS.decode_without_bom_handling_and_without_replacement(raw);
+    //GBK汉字的首字节在Shift_JIS中多为半角片假名，含假名且无半角片假名时才认为是日文
+    if let Some(name) = &shift_jis
+        && name.chars().any(|c| ('\u{3040}'..='\u{30FF}').contains(&c))
+        && !name.chars().any(|c| ('\u{FF61}'..='\u{FF9F}').contains(&c))
+    {
+        return name.to_string();
+    }
+    if let Some(name) = GBK.decode_without_bom_handling_and_without_replacement(raw) {
+        return name.to_string();
+    }
+    match shift_jis {
+        Some(name) => name.to_string(),
+        None => cp437_decode(raw),
+    }
+}
+
+fn cp437_decode(raw: &[u8]) -> String {
+    raw.iter()
+        .map(|&byte| {
+            if byte < 0x80 {
+                byte as char
+            } else {
+                CP437_HIGH[(byte - 0x80) as usize]
+            }
+        })
+        .collect()
+}
+
+// CP437中0x80-0xFF对应的字符
+const CP437_HIGH: [char; 128] = [
+    '\u{00C7}', '\u{00FC}', '\u{00E9}', '\u{00E2}', '\u{00E4}', '\u{00E0}', '\u{00E5}', '\u{00E7}',
+    '\u{00EA}', '\u{00EB}', '\u{00E8}', '\u{00EF}', '\u{00EE}', '\u{00EC}', '\u{00C4}', '\u{00C5}',
+    '\u{00C9}', '\u{00E6}', '\u{00C6}', '\u{00F4}', '\u{00F6}', '\u{00F2}', '\u{00FB}', '\u{00F9}',
+    '\u{00FF}', '\u{00D6}', '\u{00DC}', '\u{00A2}', '\u{00A3}', '\u{00A5}', '\u{20A7}', '\u{0192}',
+    '\u{00E1}', '\u{00ED}', '\u{00F3}', '\u{00FA}', '\u{00F1}', '\u{00D1}', '\u{00AA}', '\u{00BA}',
+    '\u{00BF}', '\u{2310}', '\u{00AC}', '\u{00BD}', '\u{00BC}', '\u{00A1}', '\u{00AB}', '\u{00BB}',
+    '\u{2591}', '\u{2592}', '\u{2593}', '\u{2502}', '\u{2524}', '\u{2561}', '\u{2562}', '\u{2556}',
+    '\u{2555}', '\u{2563}', '\u{2551}', '\u{2557}', '\u{255D}', '\u{255C}', '\u{255B}', '\u{2510}',
+    '\u{2514}', '\u{2534}', '\u{252C}', '\u{251C}', '\u{2500}', '\u{253C}', '\u{255E}', '\u{255F}',
+    '\u{255A}', '\u{2554}', '\u{2569}', '\u{2566}', '\u{2560}', '\u{2550}', '\u{256C}', '\u{2567}',
+    '\u{2568}', '\u{2564}', '\u{2565}', '\u{2559}', '\u{2558}', '\u{2552}', '\u{2553}', '\u{256B}',
+    '\u{256A}', '\u{2518}', '\u{250C}', '\u{2588}', '\u{2584}', '\u{258C}', '\u{2590}', '\u{2580}',
+    '\u{03B1}', '\u{00DF}', '\u{0393}', '\u{03C0}', '\u{03A3}', '\u{03C3}', '\u{00B5}', '\u{03C4}',
+    '\u{03A6}', '\u{0398}', '\u{03A9}', '\u{03B4}', '\u{221E}', '\u{03C6}', '\u{03B5}', '\u{2229}',
+    '\u{2261}', '\u{00B1}', '\u{2265}', '\u{2264}', '\u{2320}', '\u{2321}', '\u{00F7}', '\u{2248}',
+    '\u{00B0}', '\u{2219}', '\u{00B7}', '\u{221A}', '\u{207F}', '\u{00B2}', '\u{25A0}', '\u{00A0}',
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // GBK编码的"中文"
+    const GBK_NAME: &[u8] = &[0xD6, 0xD0, 0xCE, 0xC4];
+
+    #[test]
+    fn default_is_cp437() {
+        assert_eq!(NameEncoding::default(), NameEncoding::Cp437);
+        assert_eq!(NameEncoding::default().decode(GBK_NAME), "╓╨╬─");
+        assert_eq!(NameEncoding::Auto.decode(GBK_NAME), "中文");
+    }
+}
@@ -0,0 +1,263 @@
+use binrw::io::read::Read;
+use binrw::io::seek::Seek;
+use binrw::io::write::Write;
+use binrw::{BinResult, Error};
+
+use crate::codec::{PullDecoder, stream_decoder};
+use crate::crypto::Decryptor;
+use crate::directory::{CompressionMethod, Directory};
+use crate::hash::checksum_error;
+use crate::limits::ExtractLimits;
+use crate::zip::{Config, FastZip, StreamDefault};
+
+// 条目的原始数据，加密条目边读边解密
+struct EntrySource<T> {
+    data: T,
+    decryptor: Option<Decryptor>,
+}
+
+impl<T> Read for EntrySource<T>
+where
+    T: Read + Send,
+{
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            match &mut self.decryptor {
+                Some(decryptor) => decryptor.read(&mut self.data, buf).await,
+                None => self.data.read(buf).await,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move { Ok(()) }
+    }
+}
+
+/// 按需解压条目数据的读取器，读到末尾时校验CRC-32和长度，不一致时返回包含ChecksumError的io::Error。
+/// 按实际输出的字节数检查解压上限，超出时返回包含LimitError的io::Error。
+/// 读取的是数据的link，不修改条目
+pub struct EntryReader<T> {
+    source: EntrySource<T>,
+    // Store或已解压的数据为None
+    decoder: Option<PullDecoder>,
+    finished: bool,
+    hasher: crc32fast::Hasher,
+    size: u64,
+    name: String,
+    crc32: u32,
+    uncompressed_size: u64,
+    verify_crc32: bool,
+    // AE-2条目不记录CRC-32
+    ae2: bool,
+    limits: Option<ExtractLimits>,
+    // 解密后的压缩数据长度，用于检查压缩比
+    compressed_size: u64,
+}
+
+impl<T> EntryReader<T>
+where
+    T: Read + Send,
+{
+    /// 已输出的字节数
+    pub fn position(&self) -> u64 {
+        self.size
+    }
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.finished = true;
+        if !self.verify_crc32 {
+            return Ok(());
+        }
+        let actual = (self.hasher.clone().finalize(), self.size);
+        let expected = (self.crc32, self.uncompressed_size);
+        match checksum_error(&self.name, expected, actual, self.ae2) {
+            Some(error) => Err(std::io::Error::other(error)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T> Read for EntryReader<T>
+where
+    T: Read + Send,
+{
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            if buf.is_empty() || self.finished {
+                return Ok(0);
+            }
+            let len = match &mut self.decoder {
+                Some(decoder) => decoder.decode(&mut self.source, buf).await?,
+                None => self.source.read(buf).await?,
+            };
+            if len == 0 {
+                //解码器可能在读完数据前结束，读到末尾才会校验AES认证码
+                if self.decoder.is_some() {
+                    let mut rest = [0u8; 512];
+                    while self.source.read(&mut rest).await? > 0 {}
+                }
+                self.finish()?;
+            } else {
+                if let Some(limits) = &self.limits {
+                    limits
+                        .check(&self.name, self.compressed_size, self.size, len as u64)
+                        .map_err(std::io::Error::other)?;
+                    limits.add(len as u64);
+                }
+                self.hasher.update(&buf[..len]);
+                self.size += len as u64;
+            }
+            Ok(len)
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move { Ok(()) }
+    }
+}
+
+impl<T> Directory<T>
+where
+    T: Read + Write + Seek + Send + StreamDefault,
+    T::Config: Config,
+{
+    /// 按需解压的读取器，加密条目边读边解密
+    pub fn open_reader(&self) -> impl Future<Output = BinResult<EntryReader<T>>> + Send {
+        self.open_reader_with(
+            self.password.as_deref(),
+            self.limits.clone(),
+            self.verify_crc32,
+        )
+    }
+    /// 同open_reader，使用指定的密码、上限和校验设置
+    pub(crate) fn open_reader_with<'a>(
+        &'a self,
+        password: Option<&'a [u8]>,
+        limits: Option<ExtractLimits>,
+        verify_crc32: bool,
+    ) -> impl Future<Output = BinResult<EntryReader<T>>> + Send {
+        async move {
+            let result = self.create_reader(password, limits, verify_crc32).await;
+            result.map_err(|e| self.entry_error(e))
+        }
+    }
+    fn create_reader<'a>(
+        &'a self,
+        password: Option<&'a [u8]>,
+        limits: Option<ExtractLimits>,
+        verify_crc32: bool,
+    ) -> impl Future<Output = BinResult<EntryReader<T>>> + Send {
+        async move {
+            let Some(data) = &self.data else {
+                return Err(Error::AssertFail("directory data is none".to_string()));
+            };
+            let mut data = data.link().await?;
+            data.seek_start().await?;
+            let length = data.length().await?;
+            let (decryptor, method) = if self.is_encrypted() && !self.is_dir() {
+                let (decryptor, actual) = self.open_decryptor(&mut data, length, password).await?;
+                (Some(decryptor), actual)
+            } else {
+                (None, self.compression_method.clone())
+            };
+            //AES的压缩算法记录在扩展字段中
+            let compressed = if self.compression_method == CompressionMethod::AES {
+                method != CompressionMethod::Store
+            } else {
+                self.compressed()
+            };
+            let compressed_size = decryptor.as_ref().map_or(length, Decryptor::remain);
+            let decoder = if compressed {
+                stream_decoder(&method, self.flags, self.uncompressed_size)?.map(PullDecoder::new)
+            } else {
+                None
+            };
+            Ok(EntryReader {
+                source: EntrySource { data, decryptor },
+                decoder,
+                finished: false,
+                hasher: crc32fast::Hasher::new(),
+                size: 0,
+                name: String::from_utf8_lossy(&self.file_name.inner).to_string(),
+                crc32: self.crc_32_uncompressed_data,
+                uncompressed_size: self.uncompressed_size,
+                verify_crc32,
+                ae2: self.is_ae2(),
+                limits,
+                compressed_size,
+            })
+        }
+    }
+}
+
+impl<T> FastZip<T>
+where
+    T: Read + Write + Seek + Send + StreamDefault,
+    T::Config: Config,
+{
+    /// 按名称打开条目，边读边解压，条目不存在时返回None
+    pub fn open_entry(
+        &mut self,
+        name: &str,
+    ) -> impl Future<Output = BinResult<Option<EntryReader<T>>>> + Send {
+        async move {
+            self.check_entries()?;
+            if self.entry(name).await?.is_none() {
+                return Ok(None);
+            }
+            let dir = &self.directories.0[name];
+            let password = self.entry_password(name, dir);
+            // 上限和校验设置只用于这个读取器，不写回条目
+            let limits = ExtractLimits::new(self.limits.clone());
+            let reader = dir
+                .open_reader_with(password.as_deref(), Some(limits), self.verify_crc32)
+                .await?;
+            Ok(Some(reader))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::Limits;
+    use binrw::io::read::ReadExt;
+    use miniz_oxide::deflate::CompressionLevel;
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn open_entry_keeps_directories() {
+        let mut zip = FastZip::<Cursor<Vec<u8>>>::empty();
+        zip.add_file(Cursor::new(vec![7u8; 100]), "a.bin")
+            .await
+            .unwrap();
+        let mut packaged = Cursor::new(vec![]);
+        zip.package(&mut packaged, CompressionLevel::DefaultLevel)
+            .await
+            .unwrap();
+
+        let mut reader = Cursor::new(packaged.into_inner());
+        let mut zip = FastZip::parse(&mut reader).await.unwrap();
+        zip.set_limits(Limits {
+            max_total_size: Some(100),
+            ..Limits::default()
+        });
+        zip.disable_verify_crc32();
+        // 每个读取器单独计数，两次都能读完
+        for _ in 0..2 {
+            let mut entry = zip.open_entry("a.bin").await.unwrap().unwrap();
+            let mut data = vec![];
+            entry.read_to_end(&mut data).await.unwrap();
+            assert_eq!(data.len(), 100);
+        }
+        let dir = &zip.directories["a.bin"];
+        assert!(dir.limits.is_none());
+        assert!(dir.verify_crc32);
+
+        zip.set_limits(Limits {
+            max_entries: Some(0),
+            ..Limits::default()
+        });
+        assert!(zip.open_entry("a.bin").await.is_err());
+    }
+}
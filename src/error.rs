@@ -0,0 +1,286 @@
+use std::fmt::{Display, Formatter};
+
+use binrw::Error;
+
+use crate::crypto::PasswordError;
+use crate::hash::ChecksumError;
+use crate::limits::LimitError;
+use crate::path::UnsafePathError;
+
+/// 按原因区分的错误，name为条目名称，offset为条目本地头在压缩包中的位置。
+/// 与BinError可以互相转换，库内部仍以BinError返回
+#[derive(Debug)]
+pub enum ZipError {
+    /// 底层读写失败
+    Io {
+        name: Option<String>,
+        offset: Option<u64>,
+        source: std::io::Error,
+    },
+    /// 签名不匹配，不是zip或者偏移错误
+    BadMagic {
+        name: Option<String>,
+        offset: u64,
+        message: String,
+    },
+    /// 结构或压缩数据损坏
+    Corrupt {
+        name: Option<String>,
+        offset: Option<u64>,
+        message: String,
+    },
+    /// 压缩方法无法识别或对应特性未启用
+    UnsupportedMethod {
+        name: Option<String>,
+        offset: Option<u64>,
+        method: u16,
+    },
+    /// 加密方式无法识别
+    UnsupportedEncryption { name: String },
+    /// 条目已加密但没有密码
+    MissingPassword { name: String },
+    /// 密码错误
+    WrongPassword { name: String },
+    /// AES认证码不匹配，数据被篡改或损坏
+    AuthenticationFailed { name: String },
+    /// 解压后的CRC-32与头部不一致
+    Crc32Mismatch {
+        name: String,
+        offset: Option<u64>,
+        expected: u32,
+        actual: u32,
+    },
+    /// 解压后的长度与头部不一致
+    SizeMismatch {
+        name: String,
+        offset: Option<u64>,
+        expected: u64,
+        actual: u64,
+    },
+    /// 解压路径不安全
+    UnsafePath { name: String, reason: &'static str },
+    /// 超出解析或解压上限
+    Limit(LimitError),
+    /// 无法归类的错误
+    Other(Error),
+}
+
+impl ZipError {
+    pub(crate) fn corrupt(message: impl Into<String>) -> Self {
+        Self::Corrupt {
+            name: None,
+            offset: None,
+            message: message.into(),
+        }
+    }
+    /// 补充条目名称和偏移，已有的值不覆盖
+    pub fn with_entry(mut self, entry: &str, position: u64) -> Self {
+        match &mut self {
+            Self::Io { name, offset, .. }
+            | Self::Corrupt { name, offset, .. }
+            | Self::UnsupportedMethod { name, offset, .. } => {
+                name.get_or_insert_with(|| entry.to_string());
+                offset.get_or_insert(position);
+            }
+            //签名的偏移是实际读取的位置，只补充名称
+            Self::BadMagic { name, .. } => {
+                name.get_or_insert_with(|| entry.to_string());
+            }
+            Self::Crc32Mismatch { offset, .. } | Self::SizeMismatch { offset, .. } => {
+                offset.get_or_insert(position);
+            }
+            _ => {}
+        }
+        self
+    }
+    /// 出错的条目名称
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Self::Io { name, .. }
+            | Self::BadMagic { name, .. }
+            | Self::Corrupt { name, .. }
+            | Self::UnsupportedMethod { name, .. } => name.as_deref(),
+            Self::UnsupportedEncryption { name }
+            | Self::MissingPassword { name }
+            | Self::WrongPassword { name }
+            | Self::AuthenticationFailed { name }
+            | Self::Crc32Mismatch { name, .. }
+            | Self::SizeMismatch { name, .. }
+            | Self::UnsafePath { name, .. } => Some(name),
+            Self::Limit(LimitError::EntryTooLarge { name, .. })
+            | Self::Limit(LimitError::RatioTooHigh { name, .. }) => Some(name),
+            _ => None,
+        }
+    }
+    /// 出错位置在压缩包中的偏移
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Self::BadMagic { offset, .. } => Some(*offset),
+            Self::Io { offset, .. }
+            | Self::Corrupt { offset, .. }
+            | Self::UnsupportedMethod { offset, .. }
+            | Self::Crc32Mismatch { offset, .. }
+            | Self::SizeMismatch { offset, .. } => *offset,
+            _ => None,
+        }
+    }
+}
+
+impl Display for ZipError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        //LimitError的描述中已包含名称
+        if !matches!(self, Self::Limit(_))
+            && let Some(name) = self.name()
+        {
+            write!(f, "{}: ", name)?;
+        }
+        match self {
+            Self::Io { source, .. } => write!(f, "{}", source)?,
+            Self::BadMagic { message, .. } => write!(f, "{}", message)?,
+            Self::Corrupt { message, .. } => write!(f, "{}", message)?,
+            Self::UnsupportedMethod { method, .. } => {
+                write!(f, "unsupported compression method {}", method)?
+            }
+            Self::UnsupportedEncryption { .. } => write!(f, "unsupported encryption")?,
+            Self::MissingPassword { .. } => write!(f, "encrypted but no password was given")?,
+            Self::WrongPassword { .. } => write!(f, "incorrect password")?,
+            Self::AuthenticationFailed { .. } => write!(f, "authentication code mismatch")?,
+            Self::Crc32Mismatch {
+                expected, actual, ..
+            } => write!(
+                f,
+                "crc32 mismatch, expected {:08x}, got {:08x}",
+                expected, actual
+            )?,
+            Self::SizeMismatch {
+                expected, actual, ..
+            } => write!(f, "size mismatch, expected {}, got {}", expected, actual)?,
+            Self::UnsafePath { reason, .. } => write!(f, "unsafe path, {}", reason)?,
+            Self::Limit(e) => write!(f, "{}", e)?,
+            Self::Other(e) => write!(f, "{}", e)?,
+        }
+        if let Some(offset) = self.offset() {
+            write!(f, " (offset {})", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ZipError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ZipError {
+    fn from(source: std::io::Error) -> Self {
+        Self::from(Error::Io(source))
+    }
+}
+
+impl From<Error> for ZipError {
+    fn from(error: Error) -> Self {
+        match error {
+            //EntryReader、LimitWriter和Decryptor把错误包装在io::Error中
+            Error::Io(source)
+                if source.get_ref().is_some_and(|e| {
+                    e.is::<ChecksumError>() || e.is::<LimitError>() || e.is::<PasswordError>()
+                }) =>
+            {
+                match source.into_inner() {
+                    Some(inner) => Self::from(Error::Err(inner)),
+                    None => Self::corrupt("wrapped error lost"),
+                }
+            }
+            //解码器以InvalidData报告数据损坏
+            Error::Io(source) if source.kind() == std::io::ErrorKind::InvalidData => {
+                Self::corrupt(source.to_string())
+            }
+            Error::Io(source) => Self::Io {
+                name: None,
+                offset: None,
+                source,
+            },
+            Error::BadMagic(offset, message) => Self::BadMagic {
+                name: None,
+                offset,
+                message,
+            },
+            Error::AssertFail(message) => Self::corrupt(message),
+            Error::Err(boxed) => {
+                let boxed = match boxed.downcast::<ZipError>() {
+                    Ok(e) => return *e,
+                    Err(boxed) => boxed,
+                };
+                let boxed = match boxed.downcast::<PasswordError>() {
+                    Ok(e) => {
+                        return match *e {
+                            PasswordError::Missing(name) => Self::MissingPassword { name },
+                            PasswordError::Incorrect(name) => Self::WrongPassword { name },
+                            PasswordError::AuthenticationFailed(name) => {
+                                Self::AuthenticationFailed { name }
+                            }
+                            PasswordError::Unsupported(name) => {
+                                Self::UnsupportedEncryption { name }
+                            }
+                        };
+                    }
+                    Err(boxed) => boxed,
+                };
+                let boxed = match boxed.downcast::<ChecksumError>() {
+                    Ok(e) => {
+                        return match *e {
+                            ChecksumError::Crc32 {
+                                name,
+                                expected,
+                                actual,
+                            } => Self::Crc32Mismatch {
+                                name,
+                                offset: None,
+                                expected,
+                                actual,
+                            },
+                            ChecksumError::Size {
+                                name,
+                                expected,
+                                actual,
+                            } => Self::SizeMismatch {
+                                name,
+                                offset: None,
+                                expected,
+                                actual,
+                            },
+                        };
+                    }
+                    Err(boxed) => boxed,
+                };
+                let boxed = match boxed.downcast::<UnsafePathError>() {
+                    Ok(e) => {
+                        return Self::UnsafePath {
+                            name: e.name,
+                            reason: e.reason,
+                        };
+                    }
+                    Err(boxed) => boxed,
+                };
+                match boxed.downcast::<LimitError>() {
+                    Ok(e) => Self::Limit(*e),
+                    Err(boxed) => Self::Other(Error::Err(boxed)),
+                }
+            }
+            error => Self::Other(error),
+        }
+    }
+}
+
+impl From<ZipError> for Error {
+    fn from(error: ZipError) -> Self {
+        match error {
+            ZipError::Other(error) => error,
+            error => Error::Err(Box::new(error)),
+        }
+    }
+}
@@ -1,8 +1,60 @@
+use crate::directory::CompressionMethod;
 use binrw::io::read::Read;
 use binrw::io::seek::Seek;
 use binrw::io::write::Write;
-use binrw::{BinRead, BinReaderExt, BinResult, BinWrite, BinWriterExt, Endian, Error};
+use binrw::{BinRead, BinReaderExt, BinResult, BinWrite, BinWriterExt, Endian};
+use std::any::Any;
+use std::collections::HashMap;
 use std::io::Cursor;
+use std::sync::Arc;
+
+/// 自定义扩展字段，按头部ID注册后解析为具体类型
+pub trait ExtraField: Any + Send + Sync + Sized {
+    const HEADER_ID: u16;
+    /// 解析扩展数据(不含头部ID和长度)，返回None时保留原始字节
+    fn parse(data: &[u8]) -> Option<Self>;
+    fn serialize(&self) -> Vec<u8>;
+}
+
+/// 类型擦除后的自定义扩展
+pub trait CustomExtra: Any + Send + Sync {
+    fn header_id(&self) -> u16;
+    fn serialize(&self) -> Vec<u8>;
+    fn as_any(&self) -> &dyn Any;
+}
+impl<T: ExtraField> CustomExtra for T {
+    fn header_id(&self) -> u16 {
+        T::HEADER_ID
+    }
+    fn serialize(&self) -> Vec<u8> {
+        ExtraField::serialize(self)
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+type ExtraParser = fn(&[u8]) -> Option<Arc<dyn CustomExtra>>;
+
+/// 头部ID到自定义扩展解析函数的映射
+#[derive(Clone, Default)]
+pub struct ExtraRegistry(HashMap<u16, ExtraParser>);
+impl ExtraRegistry {
+    pub fn register<T: ExtraField>(&mut self) {
+        self.0.insert(T::HEADER_ID, |data| {
+            T::parse(data).map(|value| Arc::new(value) as Arc<dyn CustomExtra>)
+        });
+    }
+    pub fn contains(&self, id: u16) -> bool {
+        self.0.contains_key(&id)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn parse(&self, id: u16, data: &[u8]) -> Option<Arc<dyn CustomExtra>> {
+        self.0.get(&id).and_then(|parser| parser(data))
+    }
+}
 
 #[derive(Clone)]
 pub enum Extra {
@@ -26,6 +78,38 @@ pub enum Extra {
         uid: u32,
         gid: u32,
     },
+    // 0x0001，字段是否存在取决于头部对应值是否为0xFFFFFFFF，读取时按顺序填充
+    Zip64 {
+        uncompressed_size: Option<u64>,
+        compressed_size: Option<u64>,
+        offset: Option<u64>,
+        disk_start: Option<u32>,
+    },
+    // 0x9901，WinZip AES加密参数，compression_method为加密前的压缩算法
+    Aes {
+        version: u16,
+        vendor: u16,
+        strength: u8,
+        compression_method: CompressionMethod,
+    },
+    // 0x7075/0x6375，Info-ZIP的UTF-8名称和注释，crc32为头部中原始字节的CRC
+    UnicodePath {
+        version: u8,
+        crc32: u32,
+        name: Vec<u8>,
+    },
+    UnicodeComment {
+        version: u8,
+        crc32: u32,
+        comment: Vec<u8>,
+    },
+    // 无法识别的扩展，写出时原样保留
+    Unknown {
+        id: u16,
+        data: Vec<u8>,
+    },
+    // 通过ExtraRegistry注册的自定义扩展
+    Custom(Arc<dyn CustomExtra>),
 }
 // pub enum ExtraType {
 //     NTFS = 0x5855,
@@ -112,6 +196,63 @@ impl BinWrite for Extra {
                     }
                     0x5855
                 }
+                Extra::Zip64 {
+                    uncompressed_size,
+                    compressed_size,
+                    offset,
+                    disk_start,
+                } => {
+                    for value in [uncompressed_size, compressed_size, offset]
+                        .into_iter()
+                        .flatten()
+                    {
+                        output.write_type(value, endian).await?;
+                    }
+                    if let Some(disk_start) = disk_start {
+                        output.write_type(disk_start, endian).await?;
+                    }
+                    0x0001
+                }
+                Extra::Aes {
+                    version,
+                    vendor,
+                    strength,
+                    compression_method,
+                } => {
+                    output.write_type(version, endian).await?;
+                    output.write_type(vendor, endian).await?;
+                    output.write_type(strength, endian).await?;
+                    output.write_type(compression_method, endian).await?;
+                    0x9901
+                }
+                Extra::UnicodePath {
+                    version,
+                    crc32,
+                    name,
+                } => {
+                    output.write_type(version, endian).await?;
+                    output.write_type(crc32, endian).await?;
+                    output.write_all(name).await?;
+                    0x7075
+                }
+                Extra::UnicodeComment {
+                    version,
+                    crc32,
+                    comment,
+                } => {
+                    output.write_type(version, endian).await?;
+                    output.write_type(crc32, endian).await?;
+                    output.write_all(comment).await?;
+                    0x6375
+                }
+                Extra::Unknown { id, data } => {
+                    output.write_all(data).await?;
+                    *id
+                }
+                Extra::Custom(custom) => {
+                    output.write_all(&custom.serialize()).await?;
+                    custom.header_id()
+                }
             };
             writer.write_type(&header_id, endian).await?;
             let size = output.get_ref().len() as u16;
@@ -136,17 +277,55 @@ impl BinRead for Extra {
     {
         async move {
             let id: u16 = reader.read_type(endian).await?;
-            Ok(match id {
+            let length: u16 = reader.read_type(endian).await?;
+            let mut bytes = vec![0u8; length as usize];
+            reader.read_exact(&mut bytes).await?;
+            //无法识别或解析失败的扩展原样保留
+            Ok(match Self::parse(id, &bytes, endian).await {
+                Ok(Some(extra)) => extra,
+                _ => Self::Unknown { id, data: bytes },
+            })
+        }
+    }
+}
+impl Extra {
+    /// 扩展的头部ID
+    pub fn header_id(&self) -> u16 {
+        match self {
+            Extra::NTFS { .. } => 0x000a,
+            Extra::UnixOldExtendedTimestamp { .. } => 0x5855,
+            Extra::UnixExtendedTimestamp { .. } => 0x5455,
+            Extra::UnixAttrs { .. } => 0x7875,
+            Extra::Zip64 { .. } => 0x0001,
+            Extra::Aes { .. } => 0x9901,
+            Extra::UnicodePath { .. } => 0x7075,
+            Extra::UnicodeComment { .. } => 0x6375,
+            Extra::Unknown { id, .. } => *id,
+            Extra::Custom(custom) => custom.header_id(),
+        }
+    }
+    /// 自定义扩展的具体值
+    pub fn downcast_ref<T: ExtraField>(&self) -> Option<&T> {
+        match self {
+            Extra::Custom(custom) => custom.as_any().downcast_ref(),
+            _ => None,
+        }
+    }
+    fn parse(
+        id: u16,
+        bytes: &[u8],
+        endian: Endian,
+    ) -> impl Future<Output = BinResult<Option<Self>>> + Send {
+        async move {
+            let length = bytes.len();
+            let mut data = Cursor::new(bytes.to_vec());
+            Ok(Some(match id {
                 0x5855 => {
-                    let length: u16 = reader.read_type(endian).await?;
-                    let mut bytes = vec![0u8; length as usize];
-                    reader.read_exact(&mut bytes).await?;
-                    let mut data = Cursor::new(bytes);
                     let version = data.read_type(endian).await?;
                     let mode = data.read_type(endian).await?;
-                    let mtime = reader.read_type(endian).await?;
+                    let mtime = data.read_type(endian).await?;
                     let atime = if length >= 12 {
-                        Some(reader.read_type(endian).await?)
+                        Some(data.read_type(endian).await?)
                     } else {
                         None
                     };
@@ -158,35 +337,19 @@ impl BinRead for Extra {
                     }
                 }
                 0x5455 => {
-                    let length: u16 = reader.read_type(endian).await?;
-                    let mut bytes = vec![0u8; length as usize];
-                    reader.read_exact(&mut bytes).await?;
-                    let mut data = Cursor::new(bytes);
                     let flags: u8 = data.read_type(endian).await?;
-                    let mtime = if flags & 0x01 != 0 {
-                        if length >= 5 {
-                            Some(data.read_type(endian).await?)
-                        } else {
-                            None
-                        }
+                    let mtime = if flags & 0x01 != 0 && length >= 5 {
+                        Some(data.read_type(endian).await?)
                     } else {
                         None
                     };
-                    let atime = if flags & 0x02 != 0 {
-                        if length >= 9 {
-                            Some(data.read_type(endian).await?)
-                        } else {
-                            None
-                        }
+                    let atime = if flags & 0x02 != 0 && length >= 9 {
+                        Some(data.read_type(endian).await?)
                     } else {
                         None
                     };
-                    let ctime = if flags & 0x04 != 0 {
-                        if length >= 13 {
-                            Some(data.read_type(endian).await?)
-                        } else {
-                            None
-                        }
+                    let ctime = if flags & 0x04 != 0 && length >= 13 {
+                        Some(data.read_type(endian).await?)
                     } else {
                         None
                     };
@@ -197,24 +360,23 @@ impl BinRead for Extra {
                     }
                 }
                 0x7875 => {
-                    let length: u16 = reader.read_type(endian).await?;
-                    let mut bytes = vec![0u8; length as usize];
-                    reader.read_exact(&mut bytes).await?;
-                    let mut data = Cursor::new(bytes);
                     let _version: u8 = data.read_type(endian).await?;
-                    let _uid_size: u8 = data.read_type(endian).await?;
+                    let uid_size: u8 = data.read_type(endian).await?;
+                    //只支持4字节的uid/gid，其它长度原样保留
+                    if uid_size != 4 {
+                        return Ok(None);
+                    }
                     let uid: u32 = data.read_type(endian).await?;
-                    let _gid_size: u8 = data.read_type(endian).await?;
+                    let gid_size: u8 = data.read_type(endian).await?;
+                    if gid_size != 4 {
+                        return Ok(None);
+                    }
                     Self::UnixAttrs {
                         uid,
                         gid: data.read_type(endian).await?,
                     }
                 }
                 0x000A => {
-                    let length: u16 = reader.read_type(endian).await?;
-                    let mut bytes = vec![0u8; length as usize];
-                    reader.read_exact(&mut bytes).await?;
-                    let mut data = Cursor::new(bytes);
                     let _reserved: u32 = data.read_type(endian).await?;
                     let _tag: u16 = data.read_type(endian).await?;
                     let _size: u16 = data.read_type(endian).await?;
@@ -227,11 +389,56 @@ impl BinRead for Extra {
                         ctime,
                     }
                 }
-                _ => {
-                    let pos = reader.position().await?;
-                    return Err(Error::BadMagic(pos, format!("Extra id {} not match", id)));
+                0x0001 => {
+                    let mut remain = length;
+                    let mut values = Vec::with_capacity(3);
+                    while remain >= 8 && values.len() < 3 {
+                        values.push(data.read_type::<u64>(endian).await?);
+                        remain -= 8;
+                    }
+                    let disk_start = if remain >= 4 {
+                        Some(data.read_type(endian).await?)
+                    } else {
+                        None
+                    };
+                    let mut values = values.into_iter();
+                    Self::Zip64 {
+                        uncompressed_size: values.next(),
+                        compressed_size: values.next(),
+                        offset: values.next(),
+                        disk_start,
+                    }
+                }
+                0x9901 => Self::Aes {
+                    version: data.read_type(endian).await?,
+                    vendor: data.read_type(endian).await?,
+                    strength: data.read_type(endian).await?,
+                    compression_method: data.read_type(endian).await?,
+                },
+                0x7075 | 0x6375 => {
+                    let version: u8 = data.read_type(endian).await?;
+                    //目前只有版本1
+                    if version != 1 {
+                        return Ok(None);
+                    }
+                    let crc32: u32 = data.read_type(endian).await?;
+                    let value = bytes[data.position() as usize..].to_vec();
+                    if id == 0x7075 {
+                        Self::UnicodePath {
+                            version,
+                            crc32,
+                            name: value,
+                        }
+                    } else {
+                        Self::UnicodeComment {
+                            version,
+                            crc32,
+                            comment: value,
+                        }
+                    }
                 }
-            })
+                _ => return Ok(None),
+            }))
         }
     }
 }
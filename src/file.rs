@@ -1,17 +1,22 @@
+use crate::crypto::AesStrength;
 use crate::directory::{CompressionMethod, Name};
-use crate::extra::Extra;
-use crate::zip::{ZipModel, is_dir};
+use crate::encoding::NameEncoding;
+use crate::extra::{Extra, ExtraField, ExtraRegistry};
+use crate::options::ParseOptions;
+use crate::zip::{UTF8_FLAG, ZIP64_LIMIT, ZIP64_VERSION, ZipModel, is_dir};
 use binrw::io::read::Read;
 use binrw::io::seek::Seek;
 use binrw::io::write::Write;
-use binrw::{BinRead, BinReaderExt, BinResult, BinWrite, BinWriterExt, Endian};
+use binrw::{BinRead, BinReaderExt, BinResult, BinWrite, BinWriterExt, Endian, Error};
 use std::io::Cursor;
 
 #[derive(Debug, Clone)]
 pub struct DataDescriptor {
     pub crc32: u32,
-    pub compressed_size: u32,
-    pub uncompressed_size: u32,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    // 本地头带有ZIP64扩展时，大小字段为8字节
+    pub zip64: bool,
 }
 impl DataDescriptor {
     const MAGIC: u32 = 0x08074b50_u32;
@@ -33,19 +38,24 @@ impl BinWrite for DataDescriptor {
         async move {
             writer.write_le(&DataDescriptor::MAGIC).await?;
             writer.write_le(&self.crc32).await?;
-            writer.write_le(&self.compressed_size).await?;
-            writer.write_le(&self.uncompressed_size).await?;
+            if self.zip64 {
+                writer.write_le(&self.compressed_size).await?;
+                writer.write_le(&self.uncompressed_size).await?;
+            } else {
+                writer.write_le(&(self.compressed_size as u32)).await?;
+                writer.write_le(&(self.uncompressed_size as u32)).await?;
+            }
             Ok(())
         }
     }
 }
 impl BinRead for DataDescriptor {
-    type Args<'a> = ();
+    type Args<'a> = bool;
 
     fn read_options<'a, 'r, R>(
         reader: &'r mut R,
         _endian: Endian,
-        _args: Self::Args<'a>,
+        args: Self::Args<'a>,
     ) -> impl Future<Output = BinResult<Self>> + Send + 'r
     where
         'a: 'r,
@@ -53,11 +63,22 @@ impl BinRead for DataDescriptor {
         Self: Send + 'a,
     {
         async move {
+            let zip64 = args;
             let _signature: u32 = reader.read_le().await?;
+            let crc32: u32 = reader.read_le().await?;
+            let (compressed_size, uncompressed_size) = if zip64 {
+                (reader.read_le::<u64>().await?, reader.read_le::<u64>().await?)
+            } else {
+                (
+                    reader.read_le::<u32>().await? as u64,
+                    reader.read_le::<u32>().await? as u64,
+                )
+            };
             Ok(Self {
-                crc32: reader.read_le().await?,
-                compressed_size: reader.read_le().await?,
-                uncompressed_size: reader.read_le().await?,
+                crc32,
+                compressed_size,
+                uncompressed_size,
+                zip64,
             })
         }
     }
@@ -79,15 +100,17 @@ pub struct ZipFile {
     pub last_modification_date: u16,
     pub crc_32_uncompressed_data: u32,
     // #[bw(map = |value| if file_name.inner.ends_with(&[b'/']) {0} else {*value})]
-    pub compressed_size: u32,
+    pub compressed_size: u64,
     // #[bw(map = |value| if file_name.inner.ends_with(&[b'/']) {0} else {*value})]
-    pub uncompressed_size: u32,
+    pub uncompressed_size: u64,
     // #[bw(calc = file_name.inner.len() as u16)]
     pub file_name_length: u16,
     // #[bw(try_calc = extra_fields.bytes())]
     pub extra_field_length: u16,
     // #[br(args(file_name_length, ))]
     pub file_name: Name,
+    // 本地头中名称的原始字节，file_name会被中央目录的名称覆盖，校验时用于比较
+    pub raw_file_name: Vec<u8>,
     // #[br(args(extra_field_length))]
     // #[bw(write_with = extra_fields_write, args(file_name.inner.ends_with(&[b'/'])))]
     pub extra_fields: ExtraList,
@@ -97,8 +120,13 @@ pub struct ZipFile {
     pub data_position: u64,
 }
 
+impl ZipFile {
+    pub fn extra<T: ExtraField>(&self) -> Option<&T> {
+        self.extra_fields.extra()
+    }
+}
 impl BinWrite for ZipFile {
-    type Args<'a> = (&'a ZipModel, u32);
+    type Args<'a> = (&'a ZipModel, u64, bool);
 
     fn write_options<'a, 'w, W>(
         &'a self,
@@ -112,26 +140,33 @@ impl BinWrite for ZipFile {
         Self: Sync + 'a,
     {
         async move {
-            let (model, uncompressed_size) = args;
+            let (model, uncompressed_size, zip64) = args;
             writer.write_le(&0x04034b50_u32).await?;
             let extract_zip_spec: u8 = if is_dir(&self.file_name.inner) {
                 0x0a
             } else {
                 self.extract_zip_spec
             };
+            let extract_zip_spec = if zip64 {
+                extract_zip_spec.max(ZIP64_VERSION)
+            } else {
+                extract_zip_spec
+            };
             writer.write_le(&extract_zip_spec).await?;
             writer.write_le(&self.extract_os).await?;
             let flags = if is_dir(&self.file_name.inner) {
-                0
+                self.flags & UTF8_FLAG
             } else {
                 self.flags
             };
+            let flags = flags | utf8_flag(self.file_name.bytes());
             writer.write_le(&flags).await?;
-            let compression_method = if uncompressed_size == 0 {
-                &CompressionMethod::Store
-            } else {
-                &self.compression_method
-            };
+            let compression_method =
+                if uncompressed_size == 0 && self.compression_method != CompressionMethod::AES {
+                    &CompressionMethod::Store
+                } else {
+                    &self.compression_method
+                };
             writer.write_le(compression_method).await?;
             writer.write_le(&self.last_modification_time).await?;
             writer.write_le(&self.last_modification_date).await?;
@@ -141,30 +176,46 @@ impl BinWrite for ZipFile {
             } else {
                 self.compressed_size
             };
-            writer.write_le(&compressed_size).await?;
             let uncompressed_size = if is_dir(&self.file_name.inner) {
                 0
             } else {
                 self.uncompressed_size
             };
-            writer.write_le(&uncompressed_size).await?;
-            let file_name_length = self.file_name.inner.len() as u16;
+            let mut extra_bytes = if zip64 {
+                //本地头的ZIP64扩展必须同时包含两个大小
+                writer.write_le(&(ZIP64_LIMIT as u32)).await?;
+                writer.write_le(&(ZIP64_LIMIT as u32)).await?;
+                zip64_extra_bytes(Some(uncompressed_size), Some(compressed_size), None).await?
+            } else {
+                writer.write_le(&(compressed_size as u32)).await?;
+                writer.write_le(&(uncompressed_size as u32)).await?;
+                vec![]
+            };
+            let file_name_length = self.file_name.bytes().len() as u16;
             writer.write_le(&file_name_length).await?;
-            let extra_bytes = self.extra_fields.bytes().await?;
-            let extra_field_length: u16 = extra_bytes.len() as u16;
+            extra_bytes.extend(unicode_extra_bytes(&self.file_name, None).await?);
+            extra_bytes.extend(self.extra_fields.bytes().await?);
+            let extra_field_length = extra_length(&extra_bytes)?;
             writer.write_le(&extra_field_length).await?;
             writer.write_le(&self.file_name).await?;
             writer.write_all(&extra_bytes).await?;
             if *model == ZipModel::Bin {
                 writer.write_le(&self.data_position).await?;
-                writer.write_le(&self.data_descriptor).await?;
+                match &self.data_descriptor {
+                    Some(data_descriptor) => {
+                        writer.write_le(&true).await?;
+                        writer.write_le(&data_descriptor.zip64).await?;
+                        writer.write_le(data_descriptor).await?;
+                    }
+                    None => writer.write_le(&false).await?,
+                }
             }
             Ok(())
         }
     }
 }
 impl BinRead for ZipFile {
-    type Args<'a> = (&'a ZipModel, u32);
+    type Args<'a> = (&'a ZipModel, u64, &'a ParseOptions);
 
     fn read_options<'a, 'r, R>(
         reader: &'r mut R,
@@ -177,9 +228,15 @@ impl BinRead for ZipFile {
         Self: Send + 'a,
     {
         async move {
-            let (model, uncompressed_size) = args;
+            let (model, uncompressed_size, options) = args;
+            let pos = reader.position().await?;
             let magic: u32 = reader.read_le().await?;
-            assert_eq!(magic, 0x04034b50_u32);
+            if magic != 0x04034b50_u32 {
+                return Err(Error::BadMagic(
+                    pos,
+                    format!("magic {} not match for local file header", magic),
+                ));
+            }
             let extract_zip_spec: u8 = reader.read_le().await?;
             let extract_os: u8 = reader.read_le().await?;
             let flags: u16 = reader.read_le().await?;
@@ -191,23 +248,24 @@ impl BinRead for ZipFile {
             let last_modification_time: u16 = reader.read_le().await?;
             let last_modification_date: u16 = reader.read_le().await?;
             let crc_32_uncompressed_data: u32 = reader.read_le().await?;
-            let compressed_size: u32 = reader.read_le().await?;
-            let uncompressed_size: u32 = reader.read_le().await?;
+            let mut compressed_size = reader.read_le::<u32>().await? as u64;
+            let mut uncompressed_size = reader.read_le::<u32>().await? as u64;
             let file_name_length: u16 = reader.read_le().await?;
             let extra_field_length: u16 = reader.read_le().await?;
-            let file_name: Name = reader.read_le_args(file_name_length).await?;
-            let file_name_str = String::from_utf8_lossy(&file_name.inner)
-                .to_string()
-                .replace("\\", "/");
-            let file_name = Name {
-                inner: file_name_str.as_bytes().to_vec(),
-            };
-
-            // 把\替换成/
-            let extra_fields: ExtraList = reader.read_le_args(extra_field_length).await?;
+            let raw_file_name: Name = reader.read_le_args(file_name_length).await?;
+            let mut extra_fields: ExtraList = reader.read_le_args(extra_field_length).await?;
+            extra_fields.resolve(&options.extra_registry);
+            let file_name = decode_name(
+                &raw_file_name.inner,
+                flags,
+                &extra_fields,
+                options.name_encoding,
+            );
+            extra_fields.resolve_zip64(&mut uncompressed_size, &mut compressed_size, None)?;
             let data_position: u64 = data_position_parse(reader, endian, model).await?;
-            let data_descriptor = if *model == ZipModel::Bin {
-                reader.read_le().await?
+            let data_descriptor = if *model == ZipModel::Bin && reader.read_le::<bool>().await? {
+                let zip64: bool = reader.read_le().await?;
+                Some(reader.read_le_args(zip64).await?)
             } else {
                 None
             };
@@ -224,6 +282,7 @@ impl BinRead for ZipFile {
                 file_name_length,
                 extra_field_length,
                 file_name,
+                raw_file_name: raw_file_name.inner,
                 extra_fields,
                 data_descriptor,
                 data_position,
@@ -231,6 +290,95 @@ impl BinRead for ZipFile {
         }
     }
 }
+/// 非ASCII的UTF-8字节需要设置bit 11
+pub(crate) fn utf8_flag(bytes: &[u8]) -> u16 {
+    if !bytes.is_ascii() && std::str::from_utf8(bytes).is_ok() {
+        UTF8_FLAG
+    } else {
+        0
+    }
+}
+/// 未设置UTF-8标志时优先使用CRC匹配的Unicode Path扩展，其次按encoding解码，并把\替换成/
+pub(crate) fn decode_name(
+    raw: &[u8],
+    flags: u16,
+    extra_fields: &ExtraList,
+    encoding: NameEncoding,
+) -> Name {
+    let name = if flags & UTF8_FLAG != 0 {
+        String::from_utf8_lossy(raw).to_string()
+    } else {
+        extra_fields
+            .unicode_path(raw)
+            .unwrap_or_else(|| encoding.decode(raw))
+    };
+    //解码改变了字节时保留原始名称，打包时原样写回
+    let raw = (name.as_bytes() != raw).then(|| raw.to_vec());
+    Name {
+        inner: name.replace("\\", "/").into_bytes(),
+        raw,
+    }
+}
+// 非ASCII或按旧代码页写出的名称附带Unicode扩展，兼容不识别bit 11的工具
+pub(crate) fn unicode_extra_bytes(
+    name: &Name,
+    comment: Option<&[u8]>,
+) -> impl Future<Output = BinResult<Vec<u8>>> + Send {
+    async move {
+        let mut cursor = Cursor::new(vec![]);
+        if name.raw.is_some() || utf8_flag(name.bytes()) != 0 {
+            cursor
+                .write_le(&Extra::UnicodePath {
+                    version: 1,
+                    crc32: crc32fast::hash(name.bytes()),
+                    name: name.inner.clone(),
+                })
+                .await?;
+        }
+        if let Some(comment) = comment
+            && utf8_flag(comment) != 0
+        {
+            cursor
+                .write_le(&Extra::UnicodeComment {
+                    version: 1,
+                    crc32: crc32fast::hash(comment),
+                    comment: comment.to_vec(),
+                })
+                .await?;
+        }
+        Ok(cursor.into_inner())
+    }
+}
+// 只写出需要的字段，全部为None时返回空
+pub(crate) fn zip64_extra_bytes(
+    uncompressed_size: Option<u64>,
+    compressed_size: Option<u64>,
+    offset: Option<u64>,
+) -> impl Future<Output = BinResult<Vec<u8>>> + Send {
+    async move {
+        if uncompressed_size.is_none() && compressed_size.is_none() && offset.is_none() {
+            return Ok(vec![]);
+        }
+        let mut cursor = Cursor::new(vec![]);
+        cursor
+            .write_le(&Extra::Zip64 {
+                uncompressed_size,
+                compressed_size,
+                offset,
+                disk_start: None,
+            })
+            .await?;
+        Ok(cursor.into_inner())
+    }
+}
+pub(crate) fn extra_length(extra_bytes: &[u8]) -> BinResult<u16> {
+    u16::try_from(extra_bytes.len()).map_err(|_| {
+        Error::AssertFail(format!(
+            "extra fields length {} exceeds 65535",
+            extra_bytes.len()
+        ))
+    })
+}
 // #[binrw::writer(writer)]
 pub fn extra_fields_bytes<W: Write + Seek + Send>(
     writer: &mut W,
@@ -242,7 +390,7 @@ pub fn extra_fields_bytes<W: Write + Seek + Send>(
         let mut cursor = Cursor::new(vec![]);
         if is_dir && count == 0 {
             //修复空文件夹没有ext导致无法签名bug
-            let value = ExtraList(vec![
+            let value = ExtraList::from(vec![
                 Extra::UnixExtendedTimestamp {
                     mtime: Some(0x66C2AB60_u32),
                     atime: None,
@@ -270,7 +418,7 @@ pub fn extra_fields_write<W: Write + Seek + Send>(
     async move {
         if is_dir && value.0.len() == 0 {
             //修复空文件夹没有ext导致无法签名bug
-            let value = ExtraList(vec![
+            let value = ExtraList::from(vec![
                 Extra::UnixExtendedTimestamp {
                     mtime: Some(0x66C2AB60_u32),
                     atime: None,
@@ -304,21 +452,137 @@ pub fn data_position_parse<R: Read + Seek + Send>(
             .map_err(|e| binrw::Error::Err(Box::new(e)))
     }
 }
+/// 第二项为扩展区末尾无法解析的字节，写出时原样追加
 #[derive(Clone)]
-pub struct ExtraList(pub Vec<Extra>);
+pub struct ExtraList(pub Vec<Extra>, pub Vec<u8>);
 impl From<Vec<Extra>> for ExtraList {
     fn from(value: Vec<Extra>) -> Self {
-        ExtraList(value)
+        ExtraList(value, vec![])
     }
 }
 impl ExtraList {
     pub fn bytes(&self) -> impl Future<Output = BinResult<Vec<u8>>> + Send {
         async move {
             let mut cursor = Cursor::new(vec![]);
-            cursor.write_le(&self.0).await?;
+            for extra in &self.0 {
+                // ZIP64和Unicode扩展由写入方按实际大小和名称重新生成
+                if let Extra::Zip64 { .. }
+                | Extra::UnicodePath { .. }
+                | Extra::UnicodeComment { .. } = extra
+                {
+                    continue;
+                }
+                cursor.write_le(extra).await?;
+            }
+            cursor.write_all(&self.1).await?;
             Ok(cursor.into_inner())
         }
     }
+    /// AES扩展中的加密强度和实际压缩算法
+    pub fn aes(&self) -> Option<(AesStrength, CompressionMethod)> {
+        self.0.iter().find_map(|extra| match extra {
+            Extra::Aes {
+                strength,
+                compression_method,
+                ..
+            } => Some((AesStrength::from_u8(*strength)?, compression_method.clone())),
+            _ => None,
+        })
+    }
+    /// AE-2(厂商版本2)不记录CRC-32
+    pub fn is_ae2(&self) -> bool {
+        self.0
+            .iter()
+            .any(|extra| matches!(extra, Extra::Aes { version: 2, .. }))
+    }
+    /// 替换同一头部ID的已有扩展
+    pub fn replace(&mut self, extra: Extra) {
+        let id = extra.header_id();
+        self.0.retain(|value| value.header_id() != id);
+        self.0.push(extra);
+    }
+    /// 替换已有的AES扩展
+    pub fn set_aes(&mut self, aes: Extra) {
+        self.remove_aes();
+        self.0.push(aes);
+    }
+    pub fn remove_aes(&mut self) {
+        self.0.retain(|extra| !matches!(extra, Extra::Aes { .. }));
+    }
+    /// 将已注册头部ID的原始扩展解析为自定义类型，内置扩展不受影响
+    pub fn resolve(&mut self, registry: &ExtraRegistry) {
+        for extra in &mut self.0 {
+            if let Extra::Unknown { id, data } = extra
+                && let Some(custom) = registry.parse(*id, data)
+            {
+                *extra = Extra::Custom(custom);
+            }
+        }
+    }
+    pub fn extra<T: ExtraField>(&self) -> Option<&T> {
+        self.0.iter().find_map(|extra| extra.downcast_ref())
+    }
+    /// 替换同一头部ID的已有扩展
+    pub fn set_extra<T: ExtraField>(&mut self, value: T) {
+        self.remove_extra::<T>();
+        self.0.push(Extra::Custom(std::sync::Arc::new(value)));
+    }
+    pub fn remove_extra<T: ExtraField>(&mut self) {
+        self.0.retain(|extra| extra.header_id() != T::HEADER_ID);
+    }
+    /// CRC与头部原始名称一致时返回Unicode Path扩展中的名称
+    pub fn unicode_path(&self, raw: &[u8]) -> Option<String> {
+        self.0.iter().find_map(|extra| match extra {
+            Extra::UnicodePath { crc32, name, .. } if *crc32 == crc32fast::hash(raw) => {
+                String::from_utf8(name.clone()).ok()
+            }
+            _ => None,
+        })
+    }
+    pub fn unicode_comment(&self, raw: &[u8]) -> Option<Vec<u8>> {
+        self.0.iter().find_map(|extra| match extra {
+            Extra::UnicodeComment { crc32, comment, .. }
+                if *crc32 == crc32fast::hash(raw) && std::str::from_utf8(comment).is_ok() =>
+            {
+                Some(comment.clone())
+            }
+            _ => None,
+        })
+    }
+    pub fn has_zip64(&self) -> bool {
+        self.0.iter().any(|extra| matches!(extra, Extra::Zip64 { .. }))
+    }
+    /// 头部中为0xFFFFFFFF的字段按顺序从ZIP64扩展中取真实值，返回是否存在ZIP64扩展
+    pub fn resolve_zip64(
+        &self,
+        uncompressed_size: &mut u64,
+        compressed_size: &mut u64,
+        offset: Option<&mut u64>,
+    ) -> BinResult<bool> {
+        let Some(Extra::Zip64 {
+            uncompressed_size: zip64_uncompressed_size,
+            compressed_size: zip64_compressed_size,
+            offset: zip64_offset,
+            ..
+        }) = self.0.iter().find(|extra| matches!(extra, Extra::Zip64 { .. }))
+        else {
+            return Ok(false);
+        };
+        let mut values = [zip64_uncompressed_size, zip64_compressed_size, zip64_offset]
+            .into_iter()
+            .flatten();
+        for field in [Some(uncompressed_size), Some(compressed_size), offset]
+            .into_iter()
+            .flatten()
+        {
+            if *field == 0xFFFFFFFF {
+                *field = *values.next().ok_or_else(|| {
+                    Error::AssertFail("zip64 extra field too short".to_string())
+                })?;
+            }
+        }
+        Ok(true)
+    }
 }
 impl BinRead for ExtraList {
     type Args<'a> = u16;
@@ -334,23 +598,22 @@ impl BinRead for ExtraList {
         Self: Send + 'a,
     {
         async move {
-            let bytes = args;
+            let mut bytes = vec![0u8; args as usize];
+            reader.read_exact(&mut bytes).await?;
+            let length = bytes.len() as u64;
+            let mut data = Cursor::new(bytes);
             let mut extra_fields = Vec::new();
-            if bytes > 0 {
-                let mut total_bytes = 0;
-                loop {
-                    let position = reader.position().await?;
-                    let extra_field: Extra = reader.read_type(endian).await?;
-                    extra_fields.push(extra_field);
-
-                    let size = reader.position().await? - position;
-                    total_bytes += size;
-                    if total_bytes >= bytes as u64 {
-                        break;
-                    }
+            let mut start = 0;
+            //不足一个扩展头的尾部填充或声明长度超出扩展区的部分作为原始字节保留
+            while length - start >= 4 {
+                match data.read_type::<Extra>(endian).await {
+                    Ok(extra_field) => extra_fields.push(extra_field),
+                    Err(_) => break,
                 }
+                start = data.position();
             }
-            Ok(ExtraList(extra_fields))
+            let trailing = data.into_inner().split_off(start as usize);
+            Ok(ExtraList(extra_fields, trailing))
         }
     }
 }
@@ -372,6 +635,7 @@ impl BinWrite for ExtraList {
             for extra in &self.0 {
                 writer.write_type(extra, endian).await?;
             }
+            writer.write_all(&self.1).await?;
             Ok(())
         }
     }
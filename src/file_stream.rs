@@ -0,0 +1,504 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Cursor, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use binrw::BinResult;
+use binrw::io::read::Read;
+use binrw::io::seek::Seek;
+use binrw::io::write::Write;
+
+use crate::stream::TempCounter;
+pub use crate::zip::DEFAULT_SPILL_THRESHOLD;
+use crate::zip::{Config, StreamDefault};
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+#[cfg(unix)]
+fn write_at(file: &File, buf: &[u8], offset: u64) -> std::io::Result<usize> {
+    std::os::unix::fs::FileExt::write_at(file, buf, offset)
+}
+#[cfg(windows)]
+fn write_at(file: &File, buf: &[u8], offset: u64) -> std::io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_write(file, buf, offset)
+}
+
+pub(crate) fn seek_position(pos: u64, length: u64, seek: SeekFrom) -> std::io::Result<u64> {
+    let target = match seek {
+        SeekFrom::Start(value) => Some(value),
+        SeekFrom::End(value) => length.checked_add_signed(value),
+        SeekFrom::Current(value) => pos.checked_add_signed(value),
+    };
+    target.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+/// 文件数据的配置，temp_dir为空时临时文件创建在系统临时目录
+#[derive(Clone, Debug, Default)]
+pub struct FileConfig {
+    pub compress_size: u64,
+    pub un_compress_size: u64,
+    pub temp_dir: Option<PathBuf>,
+    pub temp_counter: Option<TempCounter>,
+    // 解析时条目直接引用该文件的区间，不复制数据
+    source: Option<(Arc<File>, u64)>,
+}
+
+impl Config for FileConfig {
+    fn compress_size(&self) -> u64 {
+        self.compress_size
+    }
+
+    fn un_compress_size(&self) -> u64 {
+        self.un_compress_size
+    }
+
+    fn compress_size_mut(&mut self, value: u64) {
+        self.compress_size = value;
+    }
+
+    fn un_compress_size_mut(&mut self, value: u64) {
+        self.un_compress_size = value;
+    }
+
+    fn temp_dir(&self) -> Option<PathBuf> {
+        self.temp_dir.clone()
+    }
+
+    fn temp_counter(&self) -> Option<TempCounter> {
+        self.temp_counter.clone()
+    }
+}
+
+fn temp_file(temp_dir: Option<&Path>) -> std::io::Result<File> {
+    //创建后即删除目录项，句柄关闭时释放空间
+    match temp_dir {
+        Some(dir) => tempfile::tempfile_in(dir),
+        None => tempfile::tempfile(),
+    }
+}
+
+// 单个临时文件已计入的长度，所有副本释放后从计数器中扣除
+struct TempSize {
+    size: AtomicU64,
+    counter: TempCounter,
+}
+
+impl TempSize {
+    fn grow(&self, end: u64) {
+        let old = self.size.fetch_max(end, Ordering::Relaxed);
+        if end > old {
+            self.counter.grow(end - old);
+        }
+    }
+}
+
+impl Drop for TempSize {
+    fn drop(&mut self) {
+        self.counter.release(*self.size.get_mut());
+    }
+}
+
+/// 基于std::fs::File的数据，按位置读写，link得到的副本有独立的读写位置。
+/// 解析时条目是源文件的只读区间，解压和新增的数据写入临时文件
+pub struct FileStream {
+    file: Arc<File>,
+    offset: u64,
+    // None表示整个文件，可写；Some为只读区间的长度
+    size: Option<u64>,
+    pos: u64,
+    config: FileConfig,
+    // 库创建且需要统计占用空间的临时文件
+    temp: Option<Arc<TempSize>>,
+}
+
+impl FileStream {
+    pub fn new(file: File, mut config: FileConfig) -> Self {
+        let file = Arc::new(file);
+        config.source = Some((file.clone(), 0));
+        Self {
+            file,
+            offset: 0,
+            size: None,
+            pos: 0,
+            config,
+            temp: None,
+        }
+    }
+    /// 在temp_dir中创建临时文件，为空时使用系统临时目录，关闭后自动删除。
+    /// counter不为None时统计该文件占用的空间
+    pub fn temp(temp_dir: Option<&Path>, counter: Option<TempCounter>) -> std::io::Result<Self> {
+        let config = FileConfig {
+            temp_dir: temp_dir.map(Path::to_path_buf),
+            temp_counter: counter,
+            ..Default::default()
+        };
+        Ok(Self::temp_with_config(temp_file(temp_dir)?, config))
+    }
+    fn temp_with_config(file: File, config: FileConfig) -> Self {
+        let temp = config.temp_counter.clone().map(|counter| {
+            Arc::new(TempSize {
+                size: AtomicU64::new(0),
+                counter,
+            })
+        });
+        let mut stream = Self::new(file, config);
+        stream.temp = temp;
+        stream
+    }
+    /// 以只读方式打开，用于解析
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::new(File::open(path)?, FileConfig::default()))
+    }
+    /// 创建或清空文件，用于打包输出
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Self::new(file, FileConfig::default()))
+    }
+    pub fn set_temp_dir(&mut self, temp_dir: Option<PathBuf>) {
+        self.config.temp_dir = temp_dir;
+    }
+    pub fn set_temp_counter(&mut self, counter: Option<TempCounter>) {
+        self.config.temp_counter = counter;
+    }
+    fn view(file: Arc<File>, offset: u64, size: u64, config: &FileConfig) -> Self {
+        let mut config = config.clone();
+        config.source = Some((file.clone(), offset));
+        Self {
+            file,
+            offset,
+            size: Some(size),
+            pos: 0,
+            config,
+            temp: None,
+        }
+    }
+    fn view_len(&self) -> std::io::Result<u64> {
+        match self.size {
+            Some(size) => Ok(size),
+            None => Ok(self.file.metadata()?.len().saturating_sub(self.offset)),
+        }
+    }
+}
+
+impl StreamDefault for FileStream {
+    type Config = FileConfig;
+
+    fn from(&self) -> impl Future<Output = BinResult<Self>> + Send {
+        Self::from_config(&self.config)
+    }
+
+    fn from_config(config: &Self::Config) -> impl Future<Output = BinResult<Self>> + Send {
+        async move {
+            let file = temp_file(config.temp_dir.as_deref())?;
+            Ok(Self::temp_with_config(file, config.clone()))
+        }
+    }
+
+    fn from_temp(
+        temp_dir: &Path,
+        config: &Self::Config,
+    ) -> impl Future<Output = BinResult<Option<Self>>> + Send {
+        async move {
+            let file = temp_file(Some(temp_dir))?;
+            Ok(Some(Self::temp_with_config(file, config.clone())))
+        }
+    }
+
+    fn from_link_config(
+        pos: u64,
+        size: u64,
+        config: &Self::Config,
+    ) -> impl Future<Output = BinResult<(Self, bool)>> + Send {
+        async move {
+            match &config.source {
+                Some((file, offset)) => {
+                    let view = Self::view(file.clone(), offset + pos, size, config);
+                    Ok((view, false))
+                }
+                None => Ok((Self::from_config(config).await?, true)),
+            }
+        }
+    }
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn link(&self) -> impl Future<Output = BinResult<Self>> + Send {
+        async move {
+            Ok(Self {
+                file: self.file.clone(),
+                offset: self.offset,
+                size: self.size,
+                pos: 0,
+                config: self.config.clone(),
+                temp: self.temp.clone(),
+            })
+        }
+    }
+}
+
+impl Read for FileStream {
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            let remain = self.view_len()?.saturating_sub(self.pos);
+            let len = buf.len().min(remain.min(usize::MAX as u64) as usize);
+            if len == 0 {
+                return Ok(0);
+            }
+            let len = read_at(&self.file, &mut buf[..len], self.offset + self.pos)?;
+            self.pos += len as u64;
+            Ok(len)
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move { Ok(()) }
+    }
+}
+
+impl Write for FileStream {
+    fn write(&mut self, buf: &[u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            if self.size.is_some() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "file view is read-only",
+                ));
+            }
+            let len = write_at(&self.file, buf, self.offset + self.pos)?;
+            self.pos += len as u64;
+            if let Some(temp) = &self.temp {
+                temp.grow(self.offset + self.pos);
+            }
+            Ok(len)
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move { Ok(()) }
+    }
+}
+
+impl Seek for FileStream {
+    fn seek(&mut self, pos: SeekFrom) -> impl Future<Output = std::io::Result<u64>> + Send {
+        async move {
+            self.pos = seek_position(self.pos, self.view_len()?, pos)?;
+            Ok(self.pos)
+        }
+    }
+}
+
+/// SpillStream的配置，实际写入超过threshold的数据转存到temp_dir中的临时文件
+#[derive(Clone, Debug)]
+pub struct SpillConfig {
+    pub compress_size: u64,
+    pub un_compress_size: u64,
+    pub threshold: u64,
+    pub temp_dir: Option<PathBuf>,
+    pub temp_counter: Option<TempCounter>,
+}
+
+impl Default for SpillConfig {
+    fn default() -> Self {
+        Self {
+            compress_size: 0,
+            un_compress_size: 0,
+            threshold: DEFAULT_SPILL_THRESHOLD,
+            temp_dir: None,
+            temp_counter: None,
+        }
+    }
+}
+
+impl Config for SpillConfig {
+    fn compress_size(&self) -> u64 {
+        self.compress_size
+    }
+
+    fn un_compress_size(&self) -> u64 {
+        self.un_compress_size
+    }
+
+    fn compress_size_mut(&mut self, value: u64) {
+        self.compress_size = value;
+    }
+
+    fn un_compress_size_mut(&mut self, value: u64) {
+        self.un_compress_size = value;
+    }
+
+    fn temp_dir(&self) -> Option<PathBuf> {
+        self.temp_dir.clone()
+    }
+
+    fn spill_threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    fn temp_counter(&self) -> Option<TempCounter> {
+        self.temp_counter.clone()
+    }
+}
+
+enum SpillInner {
+    Memory(Cursor<Vec<u8>>),
+    File(FileStream),
+}
+
+/// 先保存在内存，写入超过阈值后转存到临时文件
+pub struct SpillStream {
+    inner: SpillInner,
+    config: SpillConfig,
+}
+
+impl SpillStream {
+    pub fn memory(data: Vec<u8>, config: SpillConfig) -> Self {
+        Self {
+            inner: SpillInner::Memory(Cursor::new(data)),
+            config,
+        }
+    }
+    pub fn file(file: File, config: SpillConfig) -> Self {
+        let file_config = FileConfig {
+            temp_dir: config.temp_dir.clone(),
+            temp_counter: config.temp_counter.clone(),
+            ..Default::default()
+        };
+        Self {
+            inner: SpillInner::File(FileStream::new(file, file_config)),
+            config,
+        }
+    }
+    /// 是否已转存到临时文件
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.inner, SpillInner::File(_))
+    }
+    fn spill(&mut self) -> std::io::Result<()> {
+        if let SpillInner::Memory(cursor) = &self.inner {
+            let mut stream = FileStream::temp(
+                self.config.temp_dir.as_deref(),
+                self.config.temp_counter.clone(),
+            )?;
+            let bytes = cursor.get_ref();
+            let mut written = 0;
+            while written < bytes.len() {
+                written += write_at(&stream.file, &bytes[written..], written as u64)?;
+            }
+            if let Some(temp) = &stream.temp {
+                temp.grow(written as u64);
+            }
+            stream.pos = cursor.position();
+            self.inner = SpillInner::File(stream);
+        }
+        Ok(())
+    }
+}
+
+impl StreamDefault for SpillStream {
+    type Config = SpillConfig;
+
+    fn from(&self) -> impl Future<Output = BinResult<Self>> + Send {
+        Self::from_config(&self.config)
+    }
+
+    // 头部声明的大小不可信，先保存在内存，按实际写入的字节数转存
+    fn from_config(config: &Self::Config) -> impl Future<Output = BinResult<Self>> + Send {
+        async move { Ok(Self::memory(vec![], config.clone())) }
+    }
+
+    fn from_temp(
+        temp_dir: &Path,
+        config: &Self::Config,
+    ) -> impl Future<Output = BinResult<Option<Self>>> + Send {
+        async move {
+            let stream = FileStream::temp(Some(temp_dir), config.temp_counter.clone())?;
+            Ok(Some(Self {
+                inner: SpillInner::File(stream),
+                config: config.clone(),
+            }))
+        }
+    }
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn link(&self) -> impl Future<Output = BinResult<Self>> + Send {
+        async move {
+            let inner = match &self.inner {
+                SpillInner::Memory(cursor) => {
+                    SpillInner::Memory(Cursor::new(cursor.get_ref().clone()))
+                }
+                SpillInner::File(stream) => SpillInner::File(stream.link().await?),
+            };
+            Ok(Self {
+                inner,
+                config: self.config.clone(),
+            })
+        }
+    }
+}
+
+impl Read for SpillStream {
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            match &mut self.inner {
+                SpillInner::Memory(cursor) => std::io::Read::read(cursor, buf),
+                SpillInner::File(stream) => stream.read(buf).await,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move { Ok(()) }
+    }
+}
+
+impl Write for SpillStream {
+    fn write(&mut self, buf: &[u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            if let SpillInner::Memory(cursor) = &self.inner
+                && cursor.position() + buf.len() as u64 > self.config.threshold
+            {
+                self.spill()?;
+            }
+            match &mut self.inner {
+                SpillInner::Memory(cursor) => std::io::Write::write(cursor, buf),
+                SpillInner::File(stream) => stream.write(buf).await,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move { Ok(()) }
+    }
+}
+
+impl Seek for SpillStream {
+    fn seek(&mut self, pos: SeekFrom) -> impl Future<Output = std::io::Result<u64>> + Send {
+        async move {
+            match &mut self.inner {
+                SpillInner::Memory(cursor) => std::io::Seek::seek(cursor, pos),
+                SpillInner::File(stream) => stream.seek(pos).await,
+            }
+        }
+    }
+}
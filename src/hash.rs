@@ -1,7 +1,79 @@
+use std::fmt::{Display, Formatter};
 use std::io::SeekFrom;
 
+use binrw::Error;
 use binrw::io::{Read, Seek, Write};
 
+/// 解压输出与头部记录的CRC-32或长度不一致
+#[derive(Debug)]
+pub enum ChecksumError {
+    Crc32 {
+        name: String,
+        expected: u32,
+        actual: u32,
+    },
+    Size {
+        name: String,
+        expected: u64,
+        actual: u64,
+    },
+}
+impl Display for ChecksumError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Crc32 {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: crc32 mismatch, expected {:08x}, got {:08x}",
+                name, expected, actual
+            ),
+            Self::Size {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: size mismatch, expected {}, got {}",
+                name, expected, actual
+            ),
+        }
+    }
+}
+impl std::error::Error for ChecksumError {}
+
+impl ChecksumError {
+    pub(crate) fn into_error(self) -> Error {
+        Error::Err(Box::new(self))
+    }
+}
+
+/// 比较解压输出与头部记录的(CRC-32, 长度)。AE-2条目不记录CRC-32，由认证码校验数据，只比较长度
+pub(crate) fn checksum_error(
+    name: &str,
+    expected: (u32, u64),
+    actual: (u32, u64),
+    ae2: bool,
+) -> Option<ChecksumError> {
+    if actual.1 != expected.1 {
+        return Some(ChecksumError::Size {
+            name: name.to_string(),
+            expected: expected.1,
+            actual: actual.1,
+        });
+    }
+    if !ae2 && actual.0 != expected.0 {
+        return Some(ChecksumError::Crc32 {
+            name: name.to_string(),
+            expected: expected.0,
+            actual: actual.0,
+        });
+    }
+    None
+}
+
 #[cfg(feature = "use_openssl")]
 pub struct HashWriter<T> {
     sha1: Option<openssl::sha::Sha1>,
@@ -163,6 +235,56 @@ where
         self.inner.seek(pos)
     }
 }
+/// 解压时计算输出的CRC-32和长度
+pub struct Crc32Writer<'a, W> {
+    inner: &'a mut W,
+    crc32: Option<crc32fast::Hasher>,
+    size: u64,
+}
+impl<'a, W> Crc32Writer<'a, W>
+where
+    W: Write + Seek + Send,
+{
+    pub fn new(inner: &'a mut W, enable: bool) -> Self {
+        Crc32Writer {
+            inner,
+            crc32: enable.then(crc32fast::Hasher::new),
+            size: 0,
+        }
+    }
+    /// 未启用时返回None
+    pub fn finish(self) -> Option<(u32, u64)> {
+        let size = self.size;
+        self.crc32.map(|crc| (crc.finalize(), size))
+    }
+}
+impl<W> Write for Crc32Writer<'_, W>
+where
+    W: Write + Seek + Send,
+{
+    fn write(&mut self, buf: &[u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            let size = self.inner.write(buf).await?;
+            if let Some(crc32) = &mut self.crc32 {
+                crc32.update(&buf[..size]);
+            }
+            self.size += size as u64;
+            Ok(size)
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        self.inner.flush()
+    }
+}
+impl<W> Seek for Crc32Writer<'_, W>
+where
+    W: Write + Seek + Send,
+{
+    fn seek(&mut self, pos: SeekFrom) -> impl Future<Output = std::io::Result<u64>> + Send {
+        self.inner.seek(pos)
+    }
+}
 pub trait Hasher: Send + binrw::io::write::Write {
     fn new() -> Self;
     fn update(&mut self, data: &[u8]) -> std::io::Result<()>;
@@ -270,3 +392,23 @@ impl Write for HashWriterNull {
         async move { Ok(()) }
     }
 }
+
+/// 丢弃写入的数据，校验时作为解压输出
+#[derive(Default)]
+pub struct NullWriter;
+
+impl Seek for NullWriter {
+    fn seek(&mut self, _pos: SeekFrom) -> impl Future<Output = std::io::Result<u64>> + Send {
+        async move { Ok(0) }
+    }
+}
+
+impl Write for NullWriter {
+    fn write(&mut self, buf: &[u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move { Ok(buf.len()) }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move { Ok(()) }
+    }
+}
@@ -1,15 +1,33 @@
 extern crate alloc;
 extern crate core;
 
+pub mod codec;
+pub mod crypto;
+mod deflate64;
 pub mod directory;
+pub mod encoding;
+pub mod entry_reader;
+pub mod error;
 pub mod extra;
 pub mod file;
+#[cfg(any(unix, windows))]
+pub mod file_stream;
+pub mod options;
 pub mod zip;
 pub mod hash;
+pub mod limits;
+#[cfg(all(feature = "mmap", any(unix, windows)))]
+pub mod mmap_stream;
 pub mod package;
+pub mod path;
+pub mod stream;
+pub mod time;
 pub mod un_package;
+pub mod verify;
 pub use miniz_oxide::deflate::CompressionLevel;
 pub use directory::Directory;
+pub use entry_reader::EntryReader;
+pub use error::ZipError;
 
 pub use binrw::BinResult;
 pub use binrw::error::Error as BinError;
@@ -0,0 +1,195 @@
+use binrw::Error;
+use binrw::io::seek::Seek;
+use binrw::io::write::Write;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 解析和解压的上限，None表示不限制
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    // 一次解压所有条目的实际输出字节数之和
+    pub max_total_size: Option<u64>,
+    // 单个条目的实际输出字节数
+    pub max_entry_size: Option<u64>,
+    // 实际输出字节数与压缩数据长度之比
+    pub max_ratio: Option<u64>,
+    // 中央目录声明的条目数
+    pub max_entries: Option<u64>,
+    // 名称的原始字节数
+    pub max_name_length: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum LimitError {
+    TooManyEntries { count: u64, limit: u64 },
+    NameTooLong { length: usize, limit: usize },
+    EntryTooLarge { name: String, limit: u64 },
+    TotalTooLarge { limit: u64 },
+    RatioTooHigh { name: String, limit: u64 },
+}
+impl Display for LimitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooManyEntries { count, limit } => {
+                write!(f, "archive declares {} entries, limit is {}", count, limit)
+            }
+            Self::NameTooLong { length, limit } => {
+                write!(f, "entry name is {} bytes, limit is {}", length, limit)
+            }
+            Self::EntryTooLarge { name, limit } => {
+                write!(f, "{} inflates to more than {} bytes", name, limit)
+            }
+            Self::TotalTooLarge { limit } => {
+                write!(f, "archive inflates to more than {} bytes", limit)
+            }
+            Self::RatioTooHigh { name, limit } => {
+                write!(f, "{} exceeds compression ratio {}", name, limit)
+            }
+        }
+    }
+}
+impl std::error::Error for LimitError {}
+
+impl LimitError {
+    pub(crate) fn into_error(self) -> Error {
+        Error::Err(Box::new(self))
+    }
+}
+
+/// LimitWriter写入时产生的错误包装在io::Error中，取出后作为LimitError返回
+pub(crate) fn map_limit_error(error: Error) -> Error {
+    match error {
+        Error::Io(e) if e.get_ref().is_some_and(|inner| inner.is::<LimitError>()) => {
+            match e.into_inner().map(|inner| inner.downcast::<LimitError>()) {
+                Some(Ok(limit)) => limit.into_error(),
+                _ => Error::AssertFail("limit error lost".to_string()),
+            }
+        }
+        error => error,
+    }
+}
+
+/// 一次解压操作共享的上限和已输出字节数
+#[derive(Clone, Debug, Default)]
+pub struct ExtractLimits {
+    pub limits: Limits,
+    total: Arc<AtomicU64>,
+}
+
+impl ExtractLimits {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            total: Arc::new(AtomicU64::new(0)),
+        }
+    }
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+    /// 条目已输出written字节，检查再输出len字节后是否超出上限
+    pub(crate) fn check(
+        &self,
+        name: &str,
+        compressed_size: u64,
+        written: u64,
+        len: u64,
+    ) -> Result<(), LimitError> {
+        let limits = &self.limits;
+        let written = written + len;
+        if let Some(limit) = limits.max_entry_size
+            && written > limit
+        {
+            return Err(LimitError::EntryTooLarge {
+                name: name.to_string(),
+                limit,
+            });
+        }
+        if let Some(limit) = limits.max_ratio
+            && written > compressed_size.max(1).saturating_mul(limit)
+        {
+            return Err(LimitError::RatioTooHigh {
+                name: name.to_string(),
+                limit,
+            });
+        }
+        if let Some(limit) = limits.max_total_size
+            && self.total() + len > limit
+        {
+            return Err(LimitError::TotalTooLarge { limit });
+        }
+        Ok(())
+    }
+    pub(crate) fn add(&self, len: u64) {
+        self.total.fetch_add(len, Ordering::Relaxed);
+    }
+}
+
+/// 按实际写出的字节数检查上限，不信任头部声明的大小
+pub struct LimitWriter<'a, W> {
+    inner: &'a mut W,
+    limits: Option<&'a ExtractLimits>,
+    name: &'a str,
+    compressed_size: u64,
+    written: u64,
+}
+
+impl<'a, W> LimitWriter<'a, W>
+where
+    W: Write + Seek + Send,
+{
+    pub fn new(
+        inner: &'a mut W,
+        limits: Option<&'a ExtractLimits>,
+        name: &'a str,
+        compressed_size: u64,
+    ) -> Self {
+        Self {
+            inner,
+            limits,
+            name,
+            compressed_size,
+            written: 0,
+        }
+    }
+    fn check(&self, len: u64) -> Result<(), LimitError> {
+        match self.limits {
+            Some(extract) => extract.check(self.name, self.compressed_size, self.written, len),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W> Write for LimitWriter<'_, W>
+where
+    W: Write + Seek + Send,
+{
+    fn write(&mut self, buf: &[u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            self.check(buf.len() as u64)
+                .map_err(std::io::Error::other)?;
+            let len = self.inner.write(buf).await?;
+            self.written += len as u64;
+            if let Some(extract) = self.limits {
+                extract.add(len as u64);
+            }
+            Ok(len)
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move { self.inner.flush().await }
+    }
+}
+
+impl<W> Seek for LimitWriter<'_, W>
+where
+    W: Write + Seek + Send,
+{
+    fn seek(
+        &mut self,
+        pos: std::io::SeekFrom,
+    ) -> impl Future<Output = std::io::Result<u64>> + Send {
+        async move { self.inner.seek(pos).await }
+    }
+}
@@ -0,0 +1,305 @@
+use std::fs::File;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use binrw::BinResult;
+use binrw::io::read::Read;
+use binrw::io::seek::Seek;
+use binrw::io::write::Write;
+use memmap2::Mmap;
+
+use crate::file_stream::{SpillConfig, SpillStream, seek_position};
+use crate::stream::TempCounter;
+use crate::zip::{Config, DEFAULT_SPILL_THRESHOLD, StreamDefault};
+
+/// 内存映射数据的配置，解压和新增的数据超过threshold后写入temp_dir中的临时文件
+#[derive(Clone, Debug)]
+pub struct MmapConfig {
+    pub compress_size: u64,
+    pub un_compress_size: u64,
+    pub threshold: u64,
+    pub temp_dir: Option<PathBuf>,
+    pub temp_counter: Option<TempCounter>,
+    // 解析时条目直接引用该映射的区间，不复制数据
+    source: Option<(Arc<Mmap>, u64)>,
+}
+
+impl Default for MmapConfig {
+    fn default() -> Self {
+        Self {
+            compress_size: 0,
+            un_compress_size: 0,
+            threshold: DEFAULT_SPILL_THRESHOLD,
+            temp_dir: None,
+            temp_counter: None,
+            source: None,
+        }
+    }
+}
+
+impl MmapConfig {
+    fn spill_config(&self) -> SpillConfig {
+        SpillConfig {
+            compress_size: self.compress_size,
+            un_compress_size: self.un_compress_size,
+            threshold: self.threshold,
+            temp_dir: self.temp_dir.clone(),
+            temp_counter: self.temp_counter.clone(),
+        }
+    }
+    fn owned(&self) -> Self {
+        Self {
+            source: None,
+            ..self.clone()
+        }
+    }
+}
+
+impl Config for MmapConfig {
+    fn compress_size(&self) -> u64 {
+        self.compress_size
+    }
+
+    fn un_compress_size(&self) -> u64 {
+        self.un_compress_size
+    }
+
+    fn compress_size_mut(&mut self, value: u64) {
+        self.compress_size = value;
+    }
+
+    fn un_compress_size_mut(&mut self, value: u64) {
+        self.un_compress_size = value;
+    }
+
+    fn temp_dir(&self) -> Option<PathBuf> {
+        self.temp_dir.clone()
+    }
+
+    fn spill_threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    fn temp_counter(&self) -> Option<TempCounter> {
+        self.temp_counter.clone()
+    }
+}
+
+enum MmapInner {
+    Mapped {
+        map: Arc<Mmap>,
+        offset: u64,
+        size: u64,
+        pos: u64,
+    },
+    Owned(SpillStream),
+}
+
+/// 只读内存映射的压缩包。解析时每个条目都是映射的一个区间，解压和原样打包直接读取映射，
+/// 解压或新增的数据按SpillStream保存
+pub struct MmapStream {
+    inner: MmapInner,
+    config: MmapConfig,
+}
+
+impl MmapStream {
+    pub fn new(map: Mmap, mut config: MmapConfig) -> Self {
+        let map = Arc::new(map);
+        config.source = Some((map.clone(), 0));
+        Self {
+            inner: MmapInner::Mapped {
+                size: map.len() as u64,
+                map,
+                offset: 0,
+                pos: 0,
+            },
+            config,
+        }
+    }
+    /// 映射整个文件
+    ///
+    /// # Safety
+    ///
+    /// 映射存在期间(包括所有link出的流)文件不能被本进程或其他进程修改或截断，否则读取到的数据可能改变或触发SIGBUS
+    pub unsafe fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: 由调用方保证文件在映射期间不被修改
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(Self::new(map, MmapConfig::default()))
+    }
+    pub fn set_temp_dir(&mut self, temp_dir: Option<PathBuf>) {
+        self.config.temp_dir = temp_dir;
+    }
+    pub fn set_threshold(&mut self, threshold: u64) {
+        self.config.threshold = threshold;
+    }
+    pub fn set_temp_counter(&mut self, counter: Option<TempCounter>) {
+        self.config.temp_counter = counter;
+    }
+    /// 数据是否仍引用映射
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.inner, MmapInner::Mapped { .. })
+    }
+    /// 映射中的数据，已解压或新增的数据返回None
+    pub fn as_slice(&self) -> Option<&[u8]> {
+        match &self.inner {
+            MmapInner::Mapped {
+                map, offset, size, ..
+            } => Some(&map[*offset as usize..(*offset + *size) as usize]),
+            MmapInner::Owned(_) => None,
+        }
+    }
+}
+
+impl StreamDefault for MmapStream {
+    type Config = MmapConfig;
+
+    fn from(&self) -> impl Future<Output = BinResult<Self>> + Send {
+        Self::from_config(&self.config)
+    }
+
+    fn from_config(config: &Self::Config) -> impl Future<Output = BinResult<Self>> + Send {
+        async move {
+            let stream = SpillStream::from_config(&config.spill_config()).await?;
+            Ok(Self {
+                inner: MmapInner::Owned(stream),
+                config: config.owned(),
+            })
+        }
+    }
+
+    fn from_temp(
+        temp_dir: &Path,
+        config: &Self::Config,
+    ) -> impl Future<Output = BinResult<Option<Self>>> + Send {
+        async move {
+            let stream = SpillStream::from_temp(temp_dir, &config.spill_config()).await?;
+            Ok(stream.map(|stream| Self {
+                inner: MmapInner::Owned(stream),
+                config: config.owned(),
+            }))
+        }
+    }
+
+    fn from_link_config(
+        pos: u64,
+        size: u64,
+        config: &Self::Config,
+    ) -> impl Future<Output = BinResult<(Self, bool)>> + Send {
+        async move {
+            let Some((map, offset)) = &config.source else {
+                return Ok((Self::from_config(config).await?, true));
+            };
+            let start = offset.saturating_add(pos);
+            if start
+                .checked_add(size)
+                .is_none_or(|end| end > map.len() as u64)
+            {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "view runs past end of memory map",
+                )
+                .into());
+            }
+            let mut config = config.clone();
+            config.source = Some((map.clone(), start));
+            let view = Self {
+                inner: MmapInner::Mapped {
+                    map: map.clone(),
+                    offset: start,
+                    size,
+                    pos: 0,
+                },
+                config,
+            };
+            Ok((view, false))
+        }
+    }
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn link(&self) -> impl Future<Output = BinResult<Self>> + Send {
+        async move {
+            let inner = match &self.inner {
+                MmapInner::Mapped {
+                    map, offset, size, ..
+                } => MmapInner::Mapped {
+                    map: map.clone(),
+                    offset: *offset,
+                    size: *size,
+                    pos: 0,
+                },
+                MmapInner::Owned(stream) => MmapInner::Owned(stream.link().await?),
+            };
+            Ok(Self {
+                inner,
+                config: self.config.clone(),
+            })
+        }
+    }
+}
+
+impl Read for MmapStream {
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            match &mut self.inner {
+                MmapInner::Mapped {
+                    map,
+                    offset,
+                    size,
+                    pos,
+                } => {
+                    let remain = size.saturating_sub(*pos);
+                    let len = buf.len().min(remain.min(usize::MAX as u64) as usize);
+                    if len == 0 {
+                        return Ok(0);
+                    }
+                    let start = (*offset + *pos) as usize;
+                    buf[..len].copy_from_slice(&map[start..start + len]);
+                    *pos += len as u64;
+                    Ok(len)
+                }
+                MmapInner::Owned(stream) => stream.read(buf).await,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move { Ok(()) }
+    }
+}
+
+impl Write for MmapStream {
+    fn write(&mut self, buf: &[u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            match &mut self.inner {
+                MmapInner::Mapped { .. } => Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "memory map is read-only",
+                )),
+                MmapInner::Owned(stream) => stream.write(buf).await,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move { Ok(()) }
+    }
+}
+
+impl Seek for MmapStream {
+    fn seek(&mut self, seek: SeekFrom) -> impl Future<Output = std::io::Result<u64>> + Send {
+        async move {
+            match &mut self.inner {
+                MmapInner::Mapped { size, pos, .. } => {
+                    *pos = seek_position(*pos, *size, seek)?;
+                    Ok(*pos)
+                }
+                MmapInner::Owned(stream) => stream.seek(seek).await,
+            }
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use crate::encoding::NameEncoding;
+use crate::extra::{ExtraField, ExtraRegistry};
+use crate::limits::Limits;
+
+/// 解析时的可选项
+#[derive(Clone, Default)]
+pub struct ParseOptions {
+    // 未设置UTF-8标志且没有Unicode Path扩展的文件名按该方式解码
+    pub name_encoding: NameEncoding,
+    // 解析时即转换为自定义类型的扩展
+    pub extra_registry: ExtraRegistry,
+    // 条目数和名称长度在解析时检查，其余在解压时检查
+    pub limits: Limits,
+    // 只读中央目录，本地头和数据在第一次使用时读取
+    pub lazy: bool,
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn set_name_encoding(&mut self, name_encoding: NameEncoding) {
+        self.name_encoding = name_encoding;
+    }
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+    /// 开启后解析时保存reader的link，需要T支持link。内存中的压缩包改用FastZip::parse_lazy，避免复制整个缓冲区
+    pub fn set_lazy(&mut self, lazy: bool) {
+        self.lazy = lazy;
+    }
+    pub fn register_extra<E: ExtraField>(&mut self) {
+        self.extra_registry.register::<E>();
+    }
+}
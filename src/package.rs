@@ -1,5 +1,6 @@
-use crate::directory::CompressionMethod;
-use crate::file::DataDescriptor;
+use crate::directory::Directory;
+use crate::extra::Extra;
+use crate::time::{DosDateTime, EntryTimes, TimeZone, from_unix_seconds};
 use crate::zip::{Config, FastZip, StreamDefault};
 use binrw::io::bytes::NullBytesTotalCallback;
 use binrw::io::bytes::TotalBytesCallback;
@@ -150,73 +151,46 @@ where
 
             let mut files_size = 0;
             let mut directors_size = 0;
+            self.load_all().await?;
             let total_un_compress_size = self.computer_un_compress_size().await?;
             let mut callback = BytesToTotalAdapter::new(total_un_compress_size, callback);
+            let reproducible = self.reproducible;
+            if reproducible {
+                self.normalize_reproducible();
+            }
             let crc32_computer = self.crc32_computer;
+            let zip64 = self.zip64;
+            let password = self.password.clone();
+            let encryption = self.encryption;
             let config = writer.config().clone();
             let mut writer = BufWriter::with_capacity(32 * 1024, writer);
             let mut sorted_dirs: Vec<_> = self.directories.0.iter_mut().collect();
-            sorted_dirs.sort_by(|(_, a), (_, b)| b.compressed_size.cmp(&a.compressed_size));
+            //可复现模式按名称顺序写入，与并行打包的布局一致
+            if !reproducible {
+                sorted_dirs.sort_by(|(_, a), (_, b)| b.compressed_size.cmp(&a.compressed_size));
+            }
             //write LOCAL HEADER
             for (index, (_, director)) in sorted_dirs.into_iter().enumerate() {
-                let compression_level = if index < large_file_speed as usize {
+                let compression_level = if index < large_file_speed as usize && !reproducible {
                     CompressionLevel::BestSpeed
                 } else {
                     compression_level
                 };
-                director.offset_of_local_file_header = files_size as u32;
+                director.offset_of_local_file_header = files_size;
+                let encryption = director.package_encryption(password.as_deref(), encryption);
 
                 let writer_pos_before = writer.position().await?;
-                let is_dir = director.is_dir();
-                {
-                    let file = &mut director.file;
-                    if !is_dir && director.compression_method == CompressionMethod::Deflate {
-                        director.flags = 0x08;
-                        file.flags = 0x08;
-                    }
-                    writer
-                        .write_le_args(
-                            file,
-                            (&crate::zip::ZipModel::Parse, director.uncompressed_size),
-                        )
-                        .await?;
-                }
-
-                if !is_dir {
-                    if let Some((crc32, compressed_size)) = director
-                        .compress_to_writer_callback(
-                            &config,
-                            crc32_computer,
-                            compression_level,
-                            &mut writer,
-                            &mut callback,
-                        )
-                        .await?
-                    {
-                        director.compressed_size = compressed_size as u32;
-                        director.crc_32_uncompressed_data = crc32;
-                        director.file.data_descriptor = Some(DataDescriptor {
-                            crc32,
-                            compressed_size,
-                            uncompressed_size: director.uncompressed_size,
-                        });
-                    } else if let Some(data) = &mut director.data {
-                        data.seek_start().await?;
-                        binrw::io::copy(data, &mut writer).await?;
-                        if director.compression_method == CompressionMethod::Deflate {
-                            director.file.data_descriptor = Some(DataDescriptor {
-                                crc32: director.file.crc_32_uncompressed_data,
-                                compressed_size: director.file.compressed_size,
-                                uncompressed_size: director.file.uncompressed_size,
-                            });
-                            director.file.crc_32_uncompressed_data = 0;
-                            director.file.compressed_size = 0;
-                        }
-                    }
-                }
-                if let Some(data_descriptor) = &mut director.file.data_descriptor {
-                    writer.write_le(data_descriptor).await?;
-                }
+                director
+                    .write_local_entry(
+                        &config,
+                        crc32_computer,
+                        compression_level,
+                        zip64,
+                        encryption,
+                        &mut writer,
+                        &mut callback,
+                    )
+                    .await?;
                 let file_writer_length = writer.position().await? - writer_pos_before; //写入LOCAL HEADER长度
                 files_size += file_writer_length;
             }
@@ -225,19 +199,19 @@ where
             for (_, director) in &mut self.directories.0 {
                 let header_pos_before = writer.position().await?;
                 if director.file.data_descriptor.is_some() {
-                    director.flags = 0x08;
+                    director.flags |= 0x08;
                 }
                 writer
-                    .write_le_args(director, (&crate::zip::ZipModel::Parse,))
+                    .write_le_args(director, (&crate::zip::ZipModel::Parse, zip64))
                     .await?;
                 let header_pos_after = writer.position().await?;
                 directors_size += header_pos_after - header_pos_before;
             }
             callback.call(0).await?;
-            self.size = directors_size as u32;
-            self.entries = self.directories.len() as u16;
-            self.number_of_directory_disk = self.directories.len() as u16;
-            self.offset = files_size as u32;
+            self.size = directors_size;
+            self.entries = self.directories.len() as u64;
+            self.number_of_directory_disk = self.directories.len().min(0xFFFF) as u16;
+            self.offset = files_size;
             self.write_eocd(&mut writer).await?;
             writer.flush().await?;
             writer.seek_start().await?;
@@ -619,9 +593,17 @@ where
             };
             use tokio::sync::mpsc;
 
+            self.load_all().await?;
             let total_un_compress_size = self.computer_un_compress_size().await?;
             let mut callback = BytesToTotalAdapter::new(total_un_compress_size, callback);
+            let reproducible = self.reproducible;
+            if reproducible {
+                self.normalize_reproducible();
+            }
             let crc32_computer = self.crc32_computer;
+            let zip64 = self.zip64;
+            let password = self.password.clone();
+            let encryption = self.encryption;
             let config = writer.config().clone();
             let mut writer = BufWriter::with_capacity(32 * 1024, writer);
 
@@ -654,7 +636,9 @@ where
 use indexmap::IndexMap;
                     use tokio::time::interval;
 
-                    let mut stack: IndexMap<usize, (Option<T>, u64, bool)> = IndexMap::new();
+                    use crate::stream::MergeBuffer;
+
+                    let mut stack: IndexMap<usize, (Option<MergeBuffer<T>>, u64, bool)> = IndexMap::new();
                     for (file_index, _) in sorted_dir_paths.iter().enumerate() {
                         stack.insert(file_index, (None, 0, false));
                     }
@@ -677,7 +661,11 @@ use indexmap::IndexMap;
                                                     &mut stack[file_index];
                                                 *current_bytes += buf_len;
 
-                                                if active_index.is_none() {
+                                                //可复现模式下只有按顺序的下一个文件可以直接写出
+                                                if active_index.is_none()
+                                                    && (!reproducible
+                                                        || file_index == sended_sort_files.len())
+                                                {
                                                     sended_sort_files.push(file_index);
                                                     if let Some(mut data) = current_data.take() {
                                                         data.seek_start().await?;
@@ -690,7 +678,7 @@ use indexmap::IndexMap;
                                                     writer.write_all(&buf).await?;
                                                 } else {
                                                     if current_data.is_none() {
-                                                        let data_stream = T::from_config(&config).await?;
+                                                        let data_stream = MergeBuffer::new(&config).await?;
                                                         *current_data = Some(data_stream);
                                                     }
                                                     if let Some(data) = current_data {
@@ -760,6 +748,8 @@ use indexmap::IndexMap;
                         let config = config.clone();
                         let semaphore = semaphore.clone();
                         let crc32_computer = crc32_computer;
+                        let encryption =
+                            director.package_encryption(password.as_deref(), encryption);
                         let mut write_task = CompressTask {
                             file_index: index,
                             pos: 0,
@@ -767,87 +757,32 @@ use indexmap::IndexMap;
                         };
                         scope.spawn(async move {
                             use binrw::Error;
+                            use binrw::io::bytes::BytesCallbackFn;
+                            use std::pin::Pin;
 
-use crate::package::FileTask;
                             let _permit = semaphore.acquire().await.ok();
-                            let is_dir = director.is_dir();
-
-                            let mut local_header_writer = std::io::Cursor::new(vec![]);
-
-                            {
-                                use binrw::BinWriterExt;
-
-                                use crate::{directory::CompressionMethod, zip::ZipModel};
-
-                                let file = &mut director.file;
-                                if !is_dir
-                                    && director.compression_method == CompressionMethod::Deflate
-                                {
-                                    director.flags = 0x08;
-                                    file.flags = 0x08;
-                                }
-                                local_header_writer
-                                    .write_le_args(
-                                        file,
-                                        (&ZipModel::Parse, director.uncompressed_size),
-                                    )
-                                    .await?;
-                            }
-                            write_task.write_all(local_header_writer.get_ref()).await?;
-                            if !is_dir {
-                                use binrw::io::bytes::BytesCallbackFn;
-                                use std::pin::Pin;
-
-                                let mut callback = BytesCallbackFn::new(
-                                    |bytes| -> Pin<
-                                        Box<dyn std::future::Future<Output = BinResult<()>> + Send>,
-                                    > {
-                                        let tx = tx.clone();
-                                        Box::pin(async move {
-                                            let _ = tx.send(FileTask::Read { bytes }).await;
-                                            Ok(())
-                                        })
-                                    },
-                                );
-                                if let Some((crc32, compressed_size)) = director
-                                    .compress_to_writer_callback(
-                                        &config,
-                                        crc32_computer,
-                                        compression_level,
-                                        &mut write_task,
-                                        &mut callback,
-                                    )
-                                    .await?
-                                {
-                                    director.compressed_size = compressed_size as u32;
-                                    director.crc_32_uncompressed_data = crc32;
-                                    director.file.data_descriptor = Some(DataDescriptor {
-                                        crc32,
-                                        compressed_size,
-                                        uncompressed_size: director.uncompressed_size,
-                                    });
-                                } else if let Some(data) = &mut director.data {
-                                    data.seek_start().await?;
-                                    binrw::io::copy(data, &mut write_task).await?;
-                                    if director.compression_method == CompressionMethod::Deflate {
-                                        director.file.data_descriptor = Some(DataDescriptor {
-                                            crc32: director.file.crc_32_uncompressed_data,
-                                            compressed_size: director.file.compressed_size,
-                                            uncompressed_size: director.file.uncompressed_size,
-                                        });
-                                        director.file.crc_32_uncompressed_data = 0;
-                                        director.file.compressed_size = 0;
-                                    }
-                                }
-                            }
-
-                            if let Some(data_descriptor) = &mut director.file.data_descriptor {
-                                use binrw::BinWriterExt;
-
-                                let mut dd_writer = std::io::Cursor::new(vec![]);
-                                dd_writer.write_le(data_descriptor).await?;
-                                write_task.write_all(dd_writer.get_ref()).await?;
-                            }
+                            let mut callback = BytesCallbackFn::new(
+                                |bytes| -> Pin<
+                                    Box<dyn std::future::Future<Output = BinResult<()>> + Send>,
+                                > {
+                                    let tx = tx.clone();
+                                    Box::pin(async move {
+                                        let _ = tx.send(FileTask::Read { bytes }).await;
+                                        Ok(())
+                                    })
+                                },
+                            );
+                            director
+                                .write_local_entry(
+                                    &config,
+                                    crc32_computer,
+                                    compression_level,
+                                    zip64,
+                                    encryption,
+                                    &mut write_task,
+                                    &mut callback,
+                                )
+                                .await?;
                             write_task.flush().await?;
                             tx.send(FileTask::CompressDone { file_index: index })
                                 .await
@@ -874,7 +809,7 @@ use crate::package::FileTask;
             for index in sended_sort_files.clone() {
                 let name = &index_to_name[&index];
                 if let Some(d) = self.directories.0.get_mut(name) {
-                    d.offset_of_local_file_header = files_size as u32;
+                    d.offset_of_local_file_header = files_size;
                     files_size += stack[index].1;
                 } else {
                     panic!("please check code");
@@ -888,9 +823,9 @@ use crate::package::FileTask;
                 if let Some(director) = self.directories.0.get_mut(name) {
                     let header_pos_before = writer.position().await?;
                     if director.file.data_descriptor.is_some() {
-                        director.flags = 0x08;
+                        director.flags |= 0x08;
                     }
-                    writer.write_le_args(director, (&ZipModel::Parse,)).await?;
+                    writer.write_le_args(director, (&ZipModel::Parse, zip64)).await?;
                     writer.flush().await?;
                     let header_pos_after = writer.position().await?;
                     directors_size += header_pos_after - header_pos_before;
@@ -899,14 +834,136 @@ use crate::package::FileTask;
                 }
             }
             callback.call(0).await?;
-            self.size = directors_size as u32;
-            self.entries = self.directories.len() as u16;
-            self.number_of_directory_disk = self.directories.len() as u16;
-            self.offset = files_size as u32;
+            self.size = directors_size;
+            self.entries = self.directories.len() as u64;
+            self.number_of_directory_disk = self.directories.len().min(0xFFFF) as u16;
+            self.offset = files_size;
             self.write_eocd(&mut writer).await?;
             writer.flush().await?;
 
             Ok(())
         }
     }
+    /// 按名称排序，时间取SOURCE_DATE_EPOCH(未设置时为1980-01-01)，权限统一为644/755，uid/gid置0
+    pub(crate) fn normalize_reproducible(&mut self) {
+        self.directories.0.sort_keys();
+        let epoch = std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|value| value.trim().parse::<i64>().ok());
+        let modified = match epoch {
+            Some(seconds) => from_unix_seconds(seconds),
+            None => DosDateTime::MIN
+                .to_system_time(TimeZone::Utc)
+                .unwrap_or(std::time::UNIX_EPOCH),
+        };
+        for (_, dir) in &mut self.directories.0 {
+            //已加密数据的传统加密校验字节可能来自修改时间，保持原时间
+            if !(dir.compressed && dir.is_encrypted()) {
+                dir.set_times(EntryTimes::modified(modified), TimeZone::Utc);
+            }
+            Self::normalize_attributes(dir);
+        }
+    }
+    fn normalize_attributes(dir: &mut Directory<T>) {
+        let executable = dir.unix_mode().is_some_and(|mode| mode & 0o111 != 0);
+        let mode = if dir.is_dir() {
+            0o040755
+        } else if executable {
+            0o100755
+        } else {
+            0o100644
+        };
+        dir.set_dos_attributes(0);
+        dir.set_unix_mode(mode);
+        for extra in dir
+            .extra_fields
+            .0
+            .iter_mut()
+            .chain(dir.file.extra_fields.0.iter_mut())
+        {
+            if let Extra::UnixAttrs { uid, gid } = extra {
+                *uid = 0;
+                *gid = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::{from_unix_timestamp, to_unix_timestamp};
+    use binrw::io::read::ReadExt;
+    use std::io::Cursor;
+
+    const TEXT: &[u8] = b"round trip round trip round trip round trip";
+
+    #[tokio::test]
+    async fn default_options_round_trip() {
+        let mut zip = FastZip::<Cursor<Vec<u8>>>::empty();
+        zip.add_file(Cursor::new(TEXT.to_vec()), "a.txt")
+            .await
+            .unwrap();
+        zip.add_file(Cursor::new(vec![]), "empty.txt")
+            .await
+            .unwrap();
+        let mut packaged = Cursor::new(vec![]);
+        zip.package(&mut packaged, CompressionLevel::DefaultLevel)
+            .await
+            .unwrap();
+
+        let mut reader = Cursor::new(packaged.into_inner());
+        let mut zip = FastZip::parse(&mut reader).await.unwrap();
+        assert_eq!(
+            zip.directories["a.txt"].crc_32_uncompressed_data,
+            crc32fast::hash(TEXT)
+        );
+        for (name, expected) in [("a.txt", TEXT), ("empty.txt", &b""[..])] {
+            let mut entry = zip.open_entry(name).await.unwrap().unwrap();
+            let mut data = vec![];
+            entry.read_to_end(&mut data).await.unwrap();
+            assert_eq!(data, expected);
+        }
+        assert!(zip.verify().await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn ntfs_times_round_trip() {
+        let base = std::time::UNIX_EPOCH + std::time::Duration::new(1_709_296_496, 123_456_700);
+        let accessed = base + std::time::Duration::from_secs(60);
+        let created = base - std::time::Duration::from_secs(60);
+        let mut zip = FastZip::<Cursor<Vec<u8>>>::empty();
+        let times = EntryTimes {
+            modified: base,
+            accessed: Some(accessed),
+            created: Some(created),
+        };
+        zip.add_file_with_times(Cursor::new(TEXT.to_vec()), "ntfs.txt", times)
+            .await
+            .unwrap();
+        //缺少创建时间时不写NTFS扩展，只保留扩展时间戳的秒数
+        let times = EntryTimes {
+            accessed: Some(accessed),
+            ..EntryTimes::modified(base)
+        };
+        zip.add_file_with_times(Cursor::new(TEXT.to_vec()), "unix.txt", times)
+            .await
+            .unwrap();
+        let mut packaged = Cursor::new(vec![]);
+        zip.package(&mut packaged, CompressionLevel::DefaultLevel)
+            .await
+            .unwrap();
+
+        let mut reader = Cursor::new(packaged.into_inner());
+        let zip = FastZip::parse(&mut reader).await.unwrap();
+        let dir = &zip.directories["ntfs.txt"];
+        assert_eq!(dir.modified(TimeZone::Utc), Some(base));
+        assert_eq!(dir.accessed(), Some(accessed));
+        assert_eq!(dir.created(), Some(created));
+        let dir = &zip.directories["unix.txt"];
+        let seconds = |time| from_unix_timestamp(to_unix_timestamp(time));
+        assert_eq!(dir.modified(TimeZone::Utc), Some(seconds(base)));
+        assert_eq!(dir.accessed(), Some(seconds(accessed)));
+        assert_eq!(dir.created(), None);
+    }
 }
@@ -0,0 +1,133 @@
+use binrw::Error;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// 解压时遇到不安全的条目名称的处理方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathPolicy {
+    /// 返回UnsafePathError，不写入任何文件
+    #[default]
+    Reject,
+    /// 去掉绝对路径前缀、盘符、..和NUL后写入输出目录
+    Sanitize,
+}
+
+#[derive(Debug)]
+pub struct UnsafePathError {
+    pub name: String,
+    pub reason: &'static str,
+}
+impl Display for UnsafePathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unsafe entry path {:?}: {}", self.name, self.reason)
+    }
+}
+impl std::error::Error for UnsafePathError {}
+
+impl UnsafePathError {
+    pub(crate) fn into_error(self) -> Error {
+        Error::Err(Box::new(self))
+    }
+}
+
+/// 校验条目名称并返回在output下的路径，\和/都作为分隔符
+pub fn entry_path(
+    output: &Path,
+    name: &str,
+    policy: PathPolicy,
+) -> Result<PathBuf, UnsafePathError> {
+    let reject = |reason| UnsafePathError {
+        name: name.to_string(),
+        reason,
+    };
+    let sanitize = policy == PathPolicy::Sanitize;
+    if name.contains('\0') && !sanitize {
+        return Err(reject("contains NUL byte"));
+    }
+    if name.starts_with(['/', '\\']) && !sanitize {
+        return Err(reject("absolute path"));
+    }
+    let mut path = output.to_path_buf();
+    let mut empty = true;
+    for component in name.split(['/', '\\']) {
+        let mut component = component.replace('\0', "");
+        //任何位置的C:或C:foo在Windows上都会让push替换整个路径，:还可能是NTFS数据流
+        if component.contains(':') {
+            if !sanitize {
+                return Err(reject(if is_drive(&component) {
+                    "drive letter"
+                } else {
+                    "contains colon"
+                }));
+            }
+            if is_drive(&component) {
+                component.drain(..2);
+            }
+            component.retain(|c| c != ':');
+        }
+        match component.as_str() {
+            "" | "." => continue,
+            ".." if sanitize => continue,
+            ".." => return Err(reject("parent directory component")),
+            _ => {}
+        }
+        path.push(component);
+        empty = false;
+    }
+    if empty {
+        return Err(reject("empty path"));
+    }
+    if !path.starts_with(output) {
+        return Err(reject("outside output directory"));
+    }
+    Ok(path)
+}
+
+fn is_drive(component: &str) -> bool {
+    let bytes = component.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(name: &str, policy: PathPolicy) -> Result<PathBuf, UnsafePathError> {
+        entry_path(Path::new("out"), name, policy)
+    }
+
+    #[test]
+    fn drive_after_first_component() {
+        assert!(check("a/C:x", PathPolicy::Reject).is_err());
+        assert!(check("a\\C:x", PathPolicy::Reject).is_err());
+        let path = check("a/C:x", PathPolicy::Sanitize).unwrap();
+        assert_eq!(path, Path::new("out").join("a").join("x"));
+    }
+
+    #[test]
+    fn colon_in_component() {
+        assert!(check("a/ab:stream", PathPolicy::Reject).is_err());
+        let path = check("a/ab:stream", PathPolicy::Sanitize).unwrap();
+        assert_eq!(path, Path::new("out").join("a").join("abstream"));
+    }
+
+    #[test]
+    fn parent_with_backslash() {
+        assert!(check("a\\..\\..\\x", PathPolicy::Reject).is_err());
+        let path = check("a\\..\\..\\x", PathPolicy::Sanitize).unwrap();
+        assert_eq!(path, Path::new("out").join("a").join("x"));
+    }
+
+    #[test]
+    fn unc_path() {
+        assert!(check("\\\\server\\share", PathPolicy::Reject).is_err());
+        let path = check("\\\\server\\share", PathPolicy::Sanitize).unwrap();
+        assert_eq!(path, Path::new("out").join("server").join("share"));
+    }
+
+    #[test]
+    fn plain_path() {
+        let path = check("a/b/c.txt", PathPolicy::Reject).unwrap();
+        assert_eq!(path, Path::new("out").join("a").join("b").join("c.txt"));
+    }
+}
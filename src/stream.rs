@@ -0,0 +1,361 @@
+use std::io::{Cursor, SeekFrom};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use binrw::BinResult;
+use binrw::io::{Read, Seek, Write};
+
+#[cfg(all(feature = "parallel", any(unix, windows)))]
+use crate::file_stream::{SpillConfig, SpillStream};
+use crate::zip::{Config, StreamDefault};
+
+/// 内存数据的配置，大小只作为提示
+#[derive(Clone, Debug, Default)]
+pub struct MemoryConfig {
+    pub compress_size: u64,
+    pub un_compress_size: u64,
+}
+
+impl Config for MemoryConfig {
+    fn compress_size(&self) -> u64 {
+        self.compress_size
+    }
+
+    fn un_compress_size(&self) -> u64 {
+        self.un_compress_size
+    }
+
+    fn compress_size_mut(&mut self, value: u64) {
+        self.compress_size = value;
+    }
+
+    fn un_compress_size_mut(&mut self, value: u64) {
+        self.un_compress_size = value;
+    }
+
+    fn temp_dir(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+// Cursor不保存配置，统一返回默认值
+static MEMORY_CONFIG: MemoryConfig = MemoryConfig {
+    compress_size: 0,
+    un_compress_size: 0,
+};
+
+/// Vec<u8>包装成Cursor后直接作为FastZip<Cursor<Vec<u8>>>使用，所有条目都保存在内存中。
+/// MemoryConfig没有temp_dir，中间数据不会转存，需要转存时使用SpillStream
+impl StreamDefault for Cursor<Vec<u8>> {
+    type Config = MemoryConfig;
+
+    fn from(&self) -> impl Future<Output = BinResult<Self>> + Send {
+        Self::from_config(&MEMORY_CONFIG)
+    }
+
+    // 头部中的大小不可信，不按其预分配
+    fn from_config(_config: &Self::Config) -> impl Future<Output = BinResult<Self>> + Send {
+        async move { Ok(Cursor::new(vec![])) }
+    }
+
+    fn config(&self) -> &Self::Config {
+        &MEMORY_CONFIG
+    }
+
+    fn link(&self) -> impl Future<Output = BinResult<Self>> + Send {
+        async move { Ok(Cursor::new(self.get_ref().clone())) }
+    }
+}
+
+/// 库创建的临时文件占用的空间。
+/// current为尚未释放的字节数，peak为current的最大值，total为累计写入的字节数
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TempUsage {
+    pub current: u64,
+    pub peak: u64,
+    pub total: u64,
+}
+
+#[derive(Debug, Default)]
+struct TempCounterInner {
+    current: AtomicU64,
+    peak: AtomicU64,
+    total: AtomicU64,
+}
+
+/// 临时文件占用空间的计数器，clone后共享。通过Config::temp_counter提供，
+/// 每次打包或解压前放入新的计数器即可得到该次操作的用量
+#[derive(Clone, Debug, Default)]
+pub struct TempCounter(Arc<TempCounterInner>);
+
+impl TempCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn usage(&self) -> TempUsage {
+        TempUsage {
+            current: self.0.current.load(Ordering::Relaxed),
+            peak: self.0.peak.load(Ordering::Relaxed),
+            total: self.0.total.load(Ordering::Relaxed),
+        }
+    }
+    pub(crate) fn grow(&self, delta: u64) {
+        self.0.total.fetch_add(delta, Ordering::Relaxed);
+        let current = self.0.current.fetch_add(delta, Ordering::Relaxed) + delta;
+        self.0.peak.fetch_max(current, Ordering::Relaxed);
+    }
+    pub(crate) fn release(&self, size: u64) {
+        self.0.current.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+/// 库创建的中间数据，按实际写入的字节数决定是否转存。写入超过Config::spill_threshold
+/// 且配置了temp_dir时，用T::from_temp创建临时文件并拷贝已写入的数据，之后写入该文件
+pub(crate) struct TempData<T: StreamDefault> {
+    data: T,
+    config: T::Config,
+    written: u64,
+    // 已转存或不需要转存
+    settled: bool,
+}
+
+impl<T> TempData<T>
+where
+    T: Read + Write + Seek + Send + StreamDefault,
+    T::Config: Config,
+{
+    pub(crate) fn new(config: &T::Config) -> impl Future<Output = BinResult<Self>> + Send {
+        async move {
+            Ok(Self {
+                data: T::from_config(config).await?,
+                config: config.clone(),
+                written: 0,
+                settled: cfg!(not(any(unix, windows))) || config.temp_dir().is_none(),
+            })
+        }
+    }
+    pub(crate) fn into_inner(self) -> T {
+        self.data
+    }
+    #[cfg(any(unix, windows))]
+    fn spill(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move {
+            self.settled = true;
+            let Some(temp_dir) = self.config.temp_dir() else {
+                return Ok(());
+            };
+            let temp = match T::from_temp(&temp_dir, &self.config).await {
+                Ok(temp) => temp,
+                Err(binrw::Error::Io(e)) => return Err(e),
+                Err(e) => return Err(std::io::Error::other(e.to_string())),
+            };
+            let Some(mut temp) = temp else {
+                return Ok(());
+            };
+            let pos = self.data.seek(SeekFrom::Current(0)).await?;
+            self.data.seek(SeekFrom::Start(0)).await?;
+            binrw::io::copy(&mut self.data, &mut temp).await?;
+            temp.seek(SeekFrom::Start(pos)).await?;
+            self.data = temp;
+            Ok(())
+        }
+    }
+}
+
+impl<T> Read for TempData<T>
+where
+    T: Read + Write + Seek + Send + StreamDefault,
+    T::Config: Config,
+{
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        self.data.read(buf)
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        Read::flush(&mut self.data)
+    }
+}
+
+impl<T> Write for TempData<T>
+where
+    T: Read + Write + Seek + Send + StreamDefault,
+    T::Config: Config,
+{
+    fn write(&mut self, buf: &[u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            #[cfg(any(unix, windows))]
+            if !self.settled && self.written + buf.len() as u64 > self.config.spill_threshold() {
+                self.spill().await?;
+            }
+            let len = self.data.write(buf).await?;
+            self.written += len as u64;
+            Ok(len)
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        Write::flush(&mut self.data)
+    }
+}
+
+impl<T> Seek for TempData<T>
+where
+    T: Read + Write + Seek + Send + StreamDefault,
+    T::Config: Config,
+{
+    fn seek(&mut self, pos: SeekFrom) -> impl Future<Output = std::io::Result<u64>> + Send {
+        self.data.seek(pos)
+    }
+}
+
+/// 并行打包时暂存不能直接写出的条目，配置了temp_dir时先保存在内存，超过阈值后转存到temp_dir
+#[cfg(feature = "parallel")]
+pub(crate) enum MergeBuffer<T> {
+    Data(T),
+    #[cfg(any(unix, windows))]
+    Spill(SpillStream),
+}
+
+#[cfg(feature = "parallel")]
+impl<T> MergeBuffer<T>
+where
+    T: StreamDefault,
+    T::Config: Config,
+{
+    pub(crate) fn new(config: &T::Config) -> impl Future<Output = BinResult<Self>> + Send {
+        async move {
+            #[cfg(any(unix, windows))]
+            if let Some(temp_dir) = config.temp_dir() {
+                let config = SpillConfig {
+                    threshold: config.spill_threshold(),
+                    temp_dir: Some(temp_dir),
+                    temp_counter: config.temp_counter(),
+                    ..Default::default()
+                };
+                return Ok(Self::Spill(SpillStream::from_config(&config).await?));
+            }
+            Ok(Self::Data(T::from_config(config).await?))
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<T: Read + Send> Read for MergeBuffer<T> {
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            match self {
+                Self::Data(data) => data.read(buf).await,
+                #[cfg(any(unix, windows))]
+                Self::Spill(stream) => stream.read(buf).await,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move {
+            match self {
+                Self::Data(data) => Read::flush(data).await,
+                #[cfg(any(unix, windows))]
+                Self::Spill(stream) => Read::flush(stream).await,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<T: Write + Send> Write for MergeBuffer<T> {
+    fn write(&mut self, buf: &[u8]) -> impl Future<Output = std::io::Result<usize>> + Send {
+        async move {
+            match self {
+                Self::Data(data) => data.write(buf).await,
+                #[cfg(any(unix, windows))]
+                Self::Spill(stream) => stream.write(buf).await,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> impl Future<Output = std::io::Result<()>> + Send {
+        async move {
+            match self {
+                Self::Data(data) => Write::flush(data).await,
+                #[cfg(any(unix, windows))]
+                Self::Spill(stream) => Write::flush(stream).await,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<T: Seek + Send> Seek for MergeBuffer<T> {
+    fn seek(
+        &mut self,
+        pos: std::io::SeekFrom,
+    ) -> impl Future<Output = std::io::Result<u64>> + Send {
+        async move {
+            match self {
+                Self::Data(data) => data.seek(pos).await,
+                #[cfg(any(unix, windows))]
+                Self::Spill(stream) => stream.seek(pos).await,
+            }
+        }
+    }
+}
+
+#[cfg(all(test, any(unix, windows)))]
+mod tests {
+    use super::*;
+    use crate::file_stream::{SpillConfig, SpillStream};
+    use crate::zip::FastZip;
+    use miniz_oxide::deflate::CompressionLevel;
+
+    const SIZE: usize = 4096;
+
+    async fn packaged() -> Vec<u8> {
+        let data: Vec<u8> = (0..SIZE).map(|i| (i % 251) as u8).collect();
+        let mut zip = FastZip::<Cursor<Vec<u8>>>::empty();
+        zip.add_file(Cursor::new(data), "a.bin").await.unwrap();
+        let mut packaged = Cursor::new(vec![]);
+        zip.package(&mut packaged, CompressionLevel::DefaultLevel)
+            .await
+            .unwrap();
+        packaged.into_inner()
+    }
+
+    async fn decompress(
+        bytes: Vec<u8>,
+        temp_dir: PathBuf,
+        counter: &TempCounter,
+    ) -> BinResult<bool> {
+        let config = SpillConfig {
+            threshold: 1024,
+            temp_dir: Some(temp_dir),
+            temp_counter: Some(counter.clone()),
+            ..Default::default()
+        };
+        let mut reader = SpillStream::memory(bytes, config);
+        let mut zip = FastZip::parse(&mut reader).await?;
+        let dir = zip.directories.get_mut("a.bin").unwrap();
+        dir.decompressed().await?;
+        Ok(dir.data.as_ref().unwrap().is_spilled())
+    }
+
+    #[tokio::test]
+    async fn decompress_spills_to_temp_dir() {
+        let bytes = packaged().await;
+        let temp_dir = tempfile::tempdir().unwrap();
+        let counter = TempCounter::new();
+        let spilled = decompress(bytes.clone(), temp_dir.path().to_path_buf(), &counter)
+            .await
+            .unwrap();
+        assert!(spilled);
+        assert_eq!(counter.usage().total, SIZE as u64);
+        // 临时文件只能建在temp_dir中，目录不存在时解压失败
+        let missing = temp_dir.path().join("missing");
+        assert!(
+            decompress(bytes, missing, &TempCounter::new())
+                .await
+                .is_err()
+        );
+    }
+}
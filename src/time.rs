@@ -0,0 +1,287 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// 1601-01-01到1970-01-01的100纳秒数
+const NTFS_UNIX_OFFSET: u64 = 116_444_736_000_000_000;
+
+/// DOS时间不带时区，与SystemTime互转时按该策略换算
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeZone {
+    #[default]
+    Utc,
+    /// 相对UTC的秒数，东八区为8 * 3600
+    Offset(i32),
+}
+
+impl TimeZone {
+    fn offset(&self) -> i64 {
+        match self {
+            Self::Utc => 0,
+            Self::Offset(seconds) => *seconds as i64,
+        }
+    }
+}
+
+/// 头部中的修改日期和时间，精度2秒，范围1980-2107
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DosDateTime {
+    pub date: u16,
+    pub time: u16,
+}
+
+impl DosDateTime {
+    // 1980-01-01 00:00:00
+    pub const MIN: Self = Self {
+        date: 0x0021,
+        time: 0,
+    };
+    // 2107-12-31 23:59:58
+    pub const MAX: Self = Self {
+        date: 0xFF9F,
+        time: 0xBF7D,
+    };
+
+    /// 超出范围时取最近的边界值
+    pub fn from_system_time(time: SystemTime, zone: TimeZone) -> Self {
+        let seconds = unix_seconds(time) + zone.offset();
+        let (days, seconds) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+        let (year, month, day) = civil_from_days(days);
+        if year < 1980 {
+            return Self::MIN;
+        }
+        if year > 2107 {
+            return Self::MAX;
+        }
+        Self {
+            date: (((year - 1980) as u16) << 9) | ((month as u16) << 5) | day as u16,
+            time: (((seconds / 3600) as u16) << 11)
+                | (((seconds % 3600 / 60) as u16) << 5)
+                | ((seconds % 60 / 2) as u16),
+        }
+    }
+    /// 日期或时间字段非法时返回None
+    pub fn to_system_time(&self, zone: TimeZone) -> Option<SystemTime> {
+        let year = (self.date >> 9) as i64 + 1980;
+        let month = ((self.date >> 5) & 0x0F) as u32;
+        let day = (self.date & 0x1F) as u32;
+        let hour = (self.time >> 11) as i64;
+        let minute = ((self.time >> 5) & 0x3F) as i64;
+        let second = ((self.time & 0x1F) * 2) as i64;
+        if !(1..=12).contains(&month)
+            || day == 0
+            || day > days_in_month(year, month)
+            || hour > 23
+            || minute > 59
+            || second > 59
+        {
+            return None;
+        }
+        let days = days_from_civil(year, month, day);
+        let seconds = days * 86400 + hour * 3600 + minute * 60 + second - zone.offset();
+        Some(from_unix_seconds(seconds))
+    }
+}
+
+/// 新增条目时使用的时间，访问或创建时间为None时不写入对应的时间戳，也不写入NTFS扩展
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryTimes {
+    pub modified: SystemTime,
+    pub accessed: Option<SystemTime>,
+    pub created: Option<SystemTime>,
+}
+
+impl EntryTimes {
+    pub fn now() -> Self {
+        Self::modified(SystemTime::now())
+    }
+    pub fn modified(modified: SystemTime) -> Self {
+        Self {
+            modified,
+            accessed: None,
+            created: None,
+        }
+    }
+}
+
+pub fn unix_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs_f64().ceil() as i64),
+    }
+}
+
+pub fn from_unix_seconds(seconds: i64) -> SystemTime {
+    if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+    }
+}
+
+/// 扩展时间戳(0x5455)按有符号32位秒数处理，超出范围时截断
+pub fn to_unix_timestamp(time: SystemTime) -> u32 {
+    unix_seconds(time).clamp(0, i32::MAX as i64) as u32
+}
+
+pub fn from_unix_timestamp(timestamp: u32) -> SystemTime {
+    from_unix_seconds(timestamp as i32 as i64)
+}
+
+/// NTFS扩展(0x000a)的时间为1601年起的100纳秒数
+pub fn to_ntfs(time: SystemTime) -> u64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => NTFS_UNIX_OFFSET.saturating_add((duration.as_nanos() / 100) as u64),
+        Err(e) => NTFS_UNIX_OFFSET.saturating_sub((e.duration().as_nanos() / 100) as u64),
+    }
+}
+
+pub fn from_ntfs(ticks: u64) -> SystemTime {
+    if ticks >= NTFS_UNIX_OFFSET {
+        UNIX_EPOCH + Duration::from_nanos((ticks - NTFS_UNIX_OFFSET).saturating_mul(100))
+    } else {
+        UNIX_EPOCH - Duration::from_nanos((NTFS_UNIX_OFFSET - ticks).saturating_mul(100))
+    }
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// 公历日期与1970-01-01起天数互转
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-03-01 12:34:56 UTC
+    const SAMPLE: u64 = 1_709_296_496;
+
+    fn dos(date: u16, time: u16) -> DosDateTime {
+        DosDateTime { date, time }
+    }
+
+    #[test]
+    fn dos_bounds() {
+        let min = from_unix_seconds(315_532_800);
+        let max = from_unix_seconds(4_354_819_198);
+        assert_eq!(
+            DosDateTime::from_system_time(min, TimeZone::Utc),
+            DosDateTime::MIN
+        );
+        assert_eq!(
+            DosDateTime::from_system_time(max, TimeZone::Utc),
+            DosDateTime::MAX
+        );
+        assert_eq!(DosDateTime::MIN.to_system_time(TimeZone::Utc), Some(min));
+        assert_eq!(DosDateTime::MAX.to_system_time(TimeZone::Utc), Some(max));
+        //超出范围取边界值
+        let before = from_unix_seconds(315_532_800 - 1);
+        let after = from_unix_seconds(4_354_819_198 + 2);
+        assert_eq!(
+            DosDateTime::from_system_time(before, TimeZone::Utc),
+            DosDateTime::MIN
+        );
+        assert_eq!(
+            DosDateTime::from_system_time(after, TimeZone::Utc),
+            DosDateTime::MAX
+        );
+        assert_eq!(
+            DosDateTime::from_system_time(UNIX_EPOCH, TimeZone::Utc),
+            DosDateTime::MIN
+        );
+    }
+
+    #[test]
+    fn invalid_dates() {
+        // 1980-13-01
+        assert_eq!(dos((13 << 5) | 1, 0).to_system_time(TimeZone::Utc), None);
+        // 1980-00-01
+        assert_eq!(dos(1, 0).to_system_time(TimeZone::Utc), None);
+        // 1981-02-30
+        assert_eq!(
+            dos((1 << 9) | (2 << 5) | 30, 0).to_system_time(TimeZone::Utc),
+            None
+        );
+        // 1981-02-29不存在，1980-02-29是闰日
+        assert_eq!(
+            dos((1 << 9) | (2 << 5) | 29, 0).to_system_time(TimeZone::Utc),
+            None
+        );
+        assert!(
+            dos((2 << 5) | 29, 0)
+                .to_system_time(TimeZone::Utc)
+                .is_some()
+        );
+        // 24:00:00
+        assert_eq!(dos(0x0021, 24 << 11).to_system_time(TimeZone::Utc), None);
+    }
+
+    #[test]
+    fn time_zone() {
+        let time = UNIX_EPOCH + Duration::from_secs(SAMPLE);
+        let utc = DosDateTime::from_system_time(time, TimeZone::Utc);
+        assert_eq!(utc, dos(0x5861, 0x645c));
+        // 东八区20:34:56
+        let east = TimeZone::Offset(8 * 3600);
+        assert_eq!(
+            DosDateTime::from_system_time(time, east),
+            dos(0x5861, 0xa45c)
+        );
+        // 西十三区跨到闰日2024-02-29 23:34:56
+        let west = TimeZone::Offset(-13 * 3600);
+        assert_eq!(
+            DosDateTime::from_system_time(time, west),
+            dos(0x585d, 0xbc5c)
+        );
+        for zone in [TimeZone::Utc, east, west] {
+            let value = DosDateTime::from_system_time(time, zone);
+            assert_eq!(value.to_system_time(zone), Some(time));
+        }
+        //时区不一致时相差对应的偏移
+        assert_eq!(
+            utc.to_system_time(east),
+            Some(time - Duration::from_secs(8 * 3600))
+        );
+    }
+
+    #[test]
+    fn ntfs_round_trip() {
+        assert_eq!(to_ntfs(UNIX_EPOCH), NTFS_UNIX_OFFSET);
+        assert_eq!(from_ntfs(NTFS_UNIX_OFFSET), UNIX_EPOCH);
+        let time = UNIX_EPOCH + Duration::new(SAMPLE, 123_456_700);
+        assert_eq!(to_ntfs(time), 133_537_700_961_234_567);
+        assert_eq!(from_ntfs(to_ntfs(time)), time);
+        // 1601-01-01
+        let start = UNIX_EPOCH - Duration::from_secs(NTFS_UNIX_OFFSET / 10_000_000);
+        assert_eq!(to_ntfs(start), 0);
+        assert_eq!(from_ntfs(0), start);
+    }
+}
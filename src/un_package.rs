@@ -8,6 +8,7 @@ use binrw::{
     },
 };
 
+use crate::path::entry_path;
 use crate::zip::{Config, FastZip, StreamDefault};
 
 impl<T> FastZip<T>
@@ -15,7 +16,7 @@ where
     T: Read + Write + Seek + Send + StreamDefault,
     T::Config: Config,
 {
-    /// 解压到output，目录不存在则创建
+    /// 解压到output，目录不存在则创建，写入前校验所有条目的路径
     pub fn unzip<'a, F>(
         &mut self,
         output: &'a Path,
@@ -25,6 +26,15 @@ where
         F: TotalBytesCallback + Send,
     {
         async move {
+            self.load_all().await?;
+            self.apply_extract_options()?;
+            let passwords = self.entry_passwords();
+            let mut file_paths = Vec::with_capacity(self.directories.len());
+            for file_name in self.directories.0.keys() {
+                file_paths.push(
+                    entry_path(output, file_name, self.path_policy).map_err(|e| e.into_error())?,
+                );
+            }
             if !output.exists() {
                 std::fs::create_dir_all(output)?;
             }
@@ -56,11 +66,12 @@ where
                             }
                             Ok(())
                         });
-                        for (file_name, dir) in &mut self.directories.0 {
+                        for (((_, dir), file_path), password) in
+                            self.directories.0.iter_mut().zip(file_paths).zip(passwords)
+                        {
                             let tx = tx.clone();
                             let semaphore = semaphore.clone();
                             scope.spawn(async move {
-                                let file_path = output.join(file_name);
                                 if dir.is_dir() {
                                     use binrw::Error;
                                     tokio::fs::create_dir_all(&file_path)
@@ -120,7 +131,11 @@ where
                                         // binrw::io::copy(reader, writer)
                                         let file = BufWriter::with_capacity(1024 * 1024, file);
                                         let mut output = WriteCallback::new(file, callback);
-                                        dir.decompressed_with_writer(&mut output).await?;
+                                        dir.decompressed_with_writer_password(
+                                            &mut output,
+                                            password.as_deref(),
+                                        )
+                                        .await?;
                                         output.flush().await?;
                                     }
                                     Ok(())
@@ -142,8 +157,9 @@ where
 
                 let mut callback = BytesToTotalAdapter::new(total_bytes, callback);
 
-                for (file_name, dir) in &mut self.directories.0 {
-                    let file_path = output.join(file_name);
+                for (((_, dir), file_path), password) in
+                    self.directories.0.iter_mut().zip(file_paths).zip(passwords)
+                {
                     if dir.is_dir() {
                         std::fs::create_dir_all(&file_path)?;
                     } else {
@@ -166,7 +182,8 @@ where
                                 .open(file_path)?;
                             let file = BufWriter::with_capacity(1024 * 1024, file);
                             let mut output = WriteCallback::new(file, callback);
-                            dir.decompressed_with_writer(&mut output).await?;
+                            dir.decompressed_with_writer_password(&mut output, password.as_deref())
+                                .await?;
                             output.flush().await?;
                             (_, callback) = output.into_parts();
                         }
@@ -185,16 +202,20 @@ where
         F: TotalBytesCallback + Send,
     {
         async move {
+            self.load_all().await?;
+            self.apply_extract_options()?;
+            let passwords = self.entry_passwords();
             let mut total_bytes = 0;
             for (_, dir) in &mut self.directories.0 {
-                total_bytes += dir.compressed_size as u64;
+                total_bytes += dir.compressed_size;
             }
             let mut callback = BytesToTotalAdapter::new(total_bytes, callback);
 
             #[cfg(not(feature = "parallel"))]
             {
-                for (_, dir) in &mut self.directories.0 {
-                    dir.decompressed_with_callback(&mut callback).await?;
+                for ((_, dir), password) in self.directories.0.iter_mut().zip(passwords) {
+                    dir.decompressed_with_callback_password(&mut callback, password.as_deref())
+                        .await?;
                 }
             }
             #[cfg(feature = "parallel")]
@@ -214,10 +235,12 @@ where
                             }
                             Ok(())
                         });
-                        let mut sorted_dirs: Vec<_> = self.directories.0.values_mut().collect();
-                        sorted_dirs.sort_by(|a, b| b.compressed_size.cmp(&a.compressed_size));
+                        let mut sorted_dirs: Vec<_> =
+                            self.directories.0.values_mut().zip(passwords).collect();
+                        sorted_dirs
+                            .sort_by(|(a, _), (b, _)| b.compressed_size.cmp(&a.compressed_size));
 
-                        for dir in sorted_dirs {
+                        for (dir, password) in sorted_dirs {
                             let tx = tx.clone();
                             let semaphore = semaphore.clone();
                             scope.spawn(async move {
@@ -237,7 +260,11 @@ where
                                         })
                                     },
                                 );
-                                dir.decompressed_with_callback(&mut callback).await
+                                dir.decompressed_with_callback_password(
+                                    &mut callback,
+                                    password.as_deref(),
+                                )
+                                .await
                             });
                         }
                         drop(tx);
@@ -260,14 +287,20 @@ where
     where
         F: BytesCallback + Send,
     {
+        self.load_files(Some(files)).await?;
+        self.apply_extract_options()?;
         #[cfg(feature = "parallel")]
         self.decompress_files_parallel(callback, files).await?;
         #[cfg(not(feature = "parallel"))]
-        for (file_name, dir) in &mut self.directories.0 {
-            if let Some(data) = &mut dir.data {
-                data.seek_start().await?;
-                if files.contains(file_name) {
-                    dir.decompressed_with_callback(callback).await?;
+        {
+            let passwords = self.entry_passwords();
+            for ((file_name, dir), password) in self.directories.0.iter_mut().zip(passwords) {
+                if let Some(data) = &mut dir.data {
+                    data.seek_start().await?;
+                    if files.contains(file_name) {
+                        dir.decompressed_with_callback_password(callback, password.as_deref())
+                            .await?;
+                    }
                 }
             }
         }
@@ -285,6 +318,9 @@ where
         use std::collections::HashSet;
 
         use tokio::sync::mpsc;
+        self.load_files(Some(files)).await?;
+        self.apply_extract_options()?;
+        let passwords = self.entry_passwords();
         let (tx, mut rx) = mpsc::channel::<u64>(50);
         let cpu_num = std::thread::available_parallelism()
             .map(|n| n.get())
@@ -293,9 +329,9 @@ where
 
         let mut to_decompress = Vec::new();
         let file_set: HashSet<&str> = files.iter().map(|s| s.as_str()).collect();
-        for (file_name, dir) in &mut self.directories.0 {
+        for ((file_name, dir), password) in self.directories.0.iter_mut().zip(passwords) {
             if file_set.contains(file_name.as_str()) {
-                to_decompress.push(dir);
+                to_decompress.push((dir, password));
             }
         }
 
@@ -303,7 +339,7 @@ where
             return Ok(());
         }
 
-        to_decompress.sort_by(|a, b| b.compressed_size.cmp(&a.compressed_size));
+        to_decompress.sort_by(|(a, _), (b, _)| b.compressed_size.cmp(&a.compressed_size));
 
         let ((), results) = unsafe {
             async_scoped::TokioScope::scope_and_collect(|scope| {
@@ -334,7 +370,7 @@ where
                     Ok(())
                 });
 
-                for dir in to_decompress {
+                for (dir, password) in to_decompress {
                     let semaphore = semaphore.clone();
                     let tx = tx.clone();
                     scope.spawn(async move {
@@ -354,7 +390,8 @@ where
                                 })
                             },
                         );
-                        dir.decompressed_with_callback(&mut callback).await
+                        dir.decompressed_with_callback_password(&mut callback, password.as_deref())
+                            .await
                     });
                 }
                 drop(tx);
@@ -0,0 +1,409 @@
+use std::fmt::{Display, Formatter};
+
+use binrw::BinResult;
+use binrw::io::{Read, Seek, Write};
+
+use crate::directory::{CompressionMethod, Directory};
+use crate::hash::{ChecksumError, checksum_error};
+use crate::zip::{Config, FastZip, StreamDefault};
+
+/// 校验时发现的单个问题
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyIssue {
+    /// 本地头与中央目录的名称不一致
+    NameMismatch { central: String, local: String },
+    /// 本地头与中央目录的字段不一致，field为字段名
+    HeaderMismatch {
+        field: &'static str,
+        central: u64,
+        local: u64,
+    },
+    /// 数据描述符与中央目录的字段不一致，field为字段名
+    DescriptorMismatch {
+        field: &'static str,
+        central: u64,
+        descriptor: u64,
+    },
+    /// 解压后的CRC-32与头部记录不一致
+    Crc32Mismatch { expected: u32, actual: u32 },
+    /// 解压后的长度与头部记录不一致
+    SizeMismatch { expected: u64, actual: u64 },
+    /// 数据与其他条目重叠，other为重叠条目的名称
+    Overlap { other: String },
+    /// 数据超出中央目录的起始位置
+    PastCentralDirectory { end: u64, central_directory: u64 },
+    /// 解密或解压失败
+    Data(String),
+}
+impl Display for VerifyIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NameMismatch { central, local } => {
+                write!(
+                    f,
+                    "local name {:?} differs from central {:?}",
+                    local, central
+                )
+            }
+            Self::HeaderMismatch {
+                field,
+                central,
+                local,
+            } => write!(
+                f,
+                "local {} {} differs from central {}",
+                field, local, central
+            ),
+            Self::DescriptorMismatch {
+                field,
+                central,
+                descriptor,
+            } => write!(
+                f,
+                "data descriptor {} {} differs from central {}",
+                field, descriptor, central
+            ),
+            Self::Crc32Mismatch { expected, actual } => {
+                write!(
+                    f,
+                    "crc32 mismatch, expected {:08x}, got {:08x}",
+                    expected, actual
+                )
+            }
+            Self::SizeMismatch { expected, actual } => {
+                write!(f, "size mismatch, expected {}, got {}", expected, actual)
+            }
+            Self::Overlap { other } => write!(f, "data overlaps {}", other),
+            Self::PastCentralDirectory {
+                end,
+                central_directory,
+            } => write!(
+                f,
+                "data ends at {}, past central directory at {}",
+                end, central_directory
+            ),
+            Self::Data(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// 单个条目的校验结果，issues为空表示通过
+#[derive(Clone, Debug, Default)]
+pub struct EntryReport {
+    pub name: String,
+    pub issues: Vec<VerifyIssue>,
+}
+
+impl EntryReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// 按中央目录顺序排列的所有条目的校验结果
+#[derive(Clone, Debug, Default)]
+pub struct VerifyReport {
+    pub entries: Vec<EntryReport>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.entries.iter().all(EntryReport::is_ok)
+    }
+    pub fn failed(&self) -> impl Iterator<Item = &EntryReport> {
+        self.entries.iter().filter(|entry| !entry.is_ok())
+    }
+}
+
+impl<T> FastZip<T>
+where
+    T: Read + Write + Seek + Send + StreamDefault,
+    T::Config: Config,
+{
+    /// 类似unzip -t，检查头部一致性和数据范围，并把每个条目解压到空输出校验CRC-32和长度。
+    /// 遇到问题不中断，所有问题记录在报告中
+    pub fn verify(&mut self) -> impl Future<Output = BinResult<VerifyReport>> + Send {
+        async move {
+            self.load_all().await?;
+            self.apply_extract_options()?;
+            let passwords = self.entry_passwords();
+            let mut entries: Vec<EntryReport> = self
+                .directories
+                .0
+                .iter()
+                .map(|(name, dir)| EntryReport {
+                    name: name.clone(),
+                    issues: header_issues(dir),
+                })
+                .collect();
+            self.range_issues(&mut entries);
+            #[cfg(not(feature = "parallel"))]
+            {
+                for ((entry, dir), password) in entries
+                    .iter_mut()
+                    .zip(self.directories.0.values())
+                    .zip(&passwords)
+                {
+                    entry
+                        .issues
+                        .extend(data_issues(dir, password.as_deref()).await);
+                }
+            }
+            #[cfg(feature = "parallel")]
+            {
+                let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(
+                    std::thread::available_parallelism()
+                        .map(|n| n.get())
+                        .unwrap_or(4),
+                ));
+                let ((), results) = unsafe {
+                    async_scoped::TokioScope::scope_and_collect(|scope| {
+                        for (index, (dir, password)) in
+                            self.directories.0.values().zip(&passwords).enumerate()
+                        {
+                            let semaphore = semaphore.clone();
+                            scope.spawn(async move {
+                                let _permit = semaphore.acquire().await.ok();
+                                (index, data_issues(dir, password.as_deref()).await)
+                            });
+                        }
+                    })
+                }
+                .await;
+                for res in results {
+                    let (index, issues) = res.map_err(|e| binrw::Error::Err(Box::new(e)))?;
+                    entries[index].issues.extend(issues);
+                }
+            }
+            Ok(VerifyReport { entries })
+        }
+    }
+    /// 只检查从压缩包解析出的条目(data_position不为0)
+    fn range_issues(&self, entries: &mut [EntryReport]) {
+        let mut ranges: Vec<(u64, u64, usize)> = self
+            .directories
+            .0
+            .values()
+            .enumerate()
+            .filter(|(_, dir)| dir.file.data_position != 0)
+            .map(|(index, dir)| {
+                let end = dir.file.data_position.saturating_add(dir.compressed_size);
+                (dir.offset_of_local_file_header, end, index)
+            })
+            .collect();
+        ranges.sort();
+        let mut previous: Option<(u64, usize)> = None;
+        for (start, end, index) in ranges {
+            if end > self.offset {
+                entries[index]
+                    .issues
+                    .push(VerifyIssue::PastCentralDirectory {
+                        end,
+                        central_directory: self.offset,
+                    });
+            }
+            if let Some((previous_end, previous_index)) = previous
+                && start < previous_end
+            {
+                let other = entries[previous_index].name.clone();
+                entries[index].issues.push(VerifyIssue::Overlap { other });
+            }
+            if previous.is_none_or(|(previous_end, _)| end > previous_end) {
+                previous = Some((end, index));
+            }
+        }
+    }
+}
+
+fn header_issues<T>(dir: &Directory<T>) -> Vec<VerifyIssue>
+where
+    T: Read + Write + Seek + Send + StreamDefault,
+    T::Config: Config,
+{
+    let mut issues = vec![];
+    let file = &dir.file;
+    if file.data_position == 0 {
+        return issues;
+    }
+    //名称中的\在解析时被替换成/，两边按相同方式处理后比较
+    let normalize = |bytes: &[u8]| -> Vec<u8> {
+        bytes
+            .iter()
+            .map(|byte| if *byte == b'\\' { b'/' } else { *byte })
+            .collect()
+    };
+    if normalize(dir.file_name.bytes()) != normalize(&file.raw_file_name) {
+        issues.push(VerifyIssue::NameMismatch {
+            central: String::from_utf8_lossy(dir.file_name.bytes()).to_string(),
+            local: String::from_utf8_lossy(&file.raw_file_name).to_string(),
+        });
+    }
+    let mut compare = |field, central: u64, local: u64| {
+        if central != local {
+            issues.push(VerifyIssue::HeaderMismatch {
+                field,
+                central,
+                local,
+            });
+        }
+    };
+    compare("flags", dir.flags as u64, file.flags as u64);
+    //解析本地头时，中央目录记录的原始大小为0则方法按Store处理
+    if dir.uncompressed_size != 0 {
+        compare(
+            "compression method",
+            method_value(&dir.compression_method),
+            method_value(&file.compression_method),
+        );
+    }
+    let central = [
+        ("crc32", dir.crc_32_uncompressed_data as u64),
+        ("compressed size", dir.compressed_size),
+        ("uncompressed size", dir.uncompressed_size),
+    ];
+    match &file.data_descriptor {
+        //本地头中的值通常为0，与数据描述符比较
+        Some(descriptor) => {
+            let values = [
+                descriptor.crc32 as u64,
+                descriptor.compressed_size,
+                descriptor.uncompressed_size,
+            ];
+            for ((field, central), descriptor) in central.into_iter().zip(values) {
+                if central != descriptor {
+                    issues.push(VerifyIssue::DescriptorMismatch {
+                        field,
+                        central,
+                        descriptor,
+                    });
+                }
+            }
+        }
+        None => {
+            let values = [
+                file.crc_32_uncompressed_data as u64,
+                file.compressed_size,
+                file.uncompressed_size,
+            ];
+            for ((field, central), local) in central.into_iter().zip(values) {
+                compare(field, central, local);
+            }
+        }
+    }
+    issues
+}
+
+fn method_value(method: &CompressionMethod) -> u64 {
+    let value: u16 = method.clone().into();
+    value as u64
+}
+
+fn data_issues<'a, T>(
+    dir: &'a Directory<T>,
+    password: Option<&'a [u8]>,
+) -> impl Future<Output = Vec<VerifyIssue>> + Send
+where
+    T: Read + Write + Seek + Send + StreamDefault,
+    T::Config: Config,
+{
+    async move {
+        if dir.is_dir() {
+            return vec![];
+        }
+        let actual = match dir.inflate_checksum_with_password(password).await {
+            Ok(value) => value,
+            Err(e) => return vec![VerifyIssue::Data(e.to_string())],
+        };
+        let name = String::from_utf8_lossy(&dir.file_name.inner);
+        let expected = (dir.crc_32_uncompressed_data, dir.uncompressed_size);
+        match checksum_error(&name, expected, actual, dir.is_ae2()) {
+            Some(ChecksumError::Size {
+                expected, actual, ..
+            }) => vec![VerifyIssue::SizeMismatch { expected, actual }],
+            Some(ChecksumError::Crc32 {
+                expected, actual, ..
+            }) => vec![VerifyIssue::Crc32Mismatch { expected, actual }],
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Encryption;
+    use miniz_oxide::deflate::CompressionLevel;
+    use std::io::Cursor;
+
+    const TEXT: &[u8] = b"verify verify verify verify verify verify";
+
+    async fn packaged(encryption: Option<Encryption>) -> Vec<u8> {
+        let mut zip = FastZip::<Cursor<Vec<u8>>>::empty();
+        if let Some(encryption) = encryption {
+            zip.set_password("secret");
+            zip.set_encryption(encryption);
+        }
+        zip.add_file(Cursor::new(TEXT.to_vec()), "a.txt")
+            .await
+            .unwrap();
+        let mut packaged = Cursor::new(vec![]);
+        zip.package(&mut packaged, CompressionLevel::DefaultLevel)
+            .await
+            .unwrap();
+        packaged.into_inner()
+    }
+
+    #[tokio::test]
+    async fn descriptor_mismatch() {
+        let mut bytes = packaged(None).await;
+        let signature = bytes
+            .windows(4)
+            .position(|window| window == b"PK\x07\x08")
+            .unwrap();
+        bytes[signature + 4] ^= 0xff;
+        let mut reader = Cursor::new(bytes);
+        let mut zip = FastZip::parse(&mut reader).await.unwrap();
+        let report = zip.verify().await.unwrap();
+        // 中央目录的CRC-32不受描述符影响，数据校验通过
+        let crc32 = crc32fast::hash(TEXT);
+        assert_eq!(
+            report.entries[0].issues,
+            vec![VerifyIssue::DescriptorMismatch {
+                field: "crc32",
+                central: crc32 as u64,
+                descriptor: (crc32 ^ 0xff) as u64,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn encrypted_entry() {
+        let bytes = packaged(Some(Encryption::ZipCrypto)).await;
+        let mut reader = Cursor::new(bytes);
+        let mut zip = FastZip::parse(&mut reader).await.unwrap();
+        zip.set_password("secret");
+        assert!(zip.verify().await.unwrap().is_ok());
+        zip.set_password("wrong");
+        assert!(!zip.verify().await.unwrap().is_ok());
+    }
+
+    #[cfg(feature = "use_aes")]
+    #[tokio::test]
+    async fn aes_authentication() {
+        let mut bytes = packaged(Some(Encryption::default())).await;
+        let signature = bytes
+            .windows(4)
+            .position(|window| window == b"PK\x07\x08")
+            .unwrap();
+        // 认证码在数据描述符之前
+        bytes[signature - 1] ^= 0xff;
+        let mut reader = Cursor::new(bytes);
+        let mut zip = FastZip::parse(&mut reader).await.unwrap();
+        zip.set_password("secret");
+        let report = zip.verify().await.unwrap();
+        assert!(matches!(
+            report.entries[0].issues.as_slice(),
+            [VerifyIssue::Data(message)] if message.contains("authentication code mismatch")
+        ));
+    }
+}
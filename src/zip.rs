@@ -1,5 +1,12 @@
+use crate::crypto::{Encryption, PasswordProvider};
 use crate::directory::{CompressionMethod, Directory, Name};
+use crate::extra::{ExtraField, ExtraRegistry};
 use crate::file::{ExtraList, ZipFile};
+use crate::limits::{ExtractLimits, LimitError, Limits};
+use crate::options::ParseOptions;
+use crate::path::PathPolicy;
+use crate::stream::TempCounter;
+use crate::time::{DosDateTime, EntryTimes, TimeZone};
 use binrw::io::read::Read;
 use binrw::io::seek::Seek;
 use binrw::io::write::Write;
@@ -10,6 +17,16 @@ use std::collections::HashSet;
 use std::io::SeekFrom;
 use std::ops::{Deref, DerefMut};
 use std::pin::Pin;
+use std::sync::Arc;
+
+// 超过该值的大小/偏移需要写入ZIP64扩展
+pub const ZIP64_LIMIT: u64 = 0xFFFFFFFF;
+pub const ZIP64_VERSION: u8 = 0x2D; //4.5
+// general purpose flag bit 11，名称和注释为UTF-8编码
+pub const UTF8_FLAG: u16 = 0x0800;
+
+// 库创建的中间数据默认在写入超过该字节数后转存到临时文件
+pub const DEFAULT_SPILL_THRESHOLD: u64 = 64 * 1024 * 1024;
 
 pub trait Config: Sync + Send + Clone + Default {
     // type Value;
@@ -18,6 +35,14 @@ pub trait Config: Sync + Send + Clone + Default {
     fn compress_size_mut(&mut self, value: u64);
     fn un_compress_size_mut(&mut self, value: u64);
     fn temp_dir(&self) -> Option<std::path::PathBuf>;
+    /// 库创建的中间数据实际写入超过该字节数后转存到temp_dir
+    fn spill_threshold(&self) -> u64 {
+        DEFAULT_SPILL_THRESHOLD
+    }
+    /// 统计库创建的临时文件占用的空间，None表示不统计
+    fn temp_counter(&self) -> Option<TempCounter> {
+        None
+    }
 }
 
 pub trait StreamDefault: Sized + Sync {
@@ -33,6 +58,17 @@ pub trait StreamDefault: Sized + Sync {
         async move { Ok((data.await?, true)) }
     }
 
+    /// 中间数据超过Config::spill_threshold后调用，在temp_dir中创建临时文件构造数据，
+    /// 已写入的数据由库拷贝过去。返回None表示不支持，继续使用from_config创建的数据，
+    /// 默认实现不创建任何文件
+    #[cfg(any(unix, windows))]
+    fn from_temp(
+        _temp_dir: &std::path::Path,
+        _config: &Self::Config,
+    ) -> impl Future<Output = BinResult<Option<Self>>> + Send {
+        async move { Ok(None) }
+    }
+
     fn config(&self) -> &Self::Config;
 
     fn link(&self) -> impl Future<Output = BinResult<Self>> + Send;
@@ -177,6 +213,7 @@ where
     // #[bw(ignore)]
     pub config: T::Config,
     // #[bw(if(model == ZipModel::Bin))]
+    // 打包时计算条目的CRC-32，默认开启；关闭后新条目的CRC-32记为0，解压这些条目需关闭verify_crc32
     pub crc32_computer: bool,
     // #[br(parse_with = parse_eocd_offset,args(model.clone(),))]
     // #[bw(ignore)]
@@ -188,13 +225,35 @@ where
     pub directory_starts: u16,
     pub number_of_directory_disk: u16,
     // #[bw(calc = directories.len() as u16)]
-    pub entries: u16,
-    pub size: u32,
-    pub offset: u32,
+    pub entries: u64,
+    pub size: u64,
+    pub offset: u64,
     // #[bw(calc = comment.len() as u16)]
     pub comment_length: u16,
     // #[br(count = comment_length)]
     pub comment: Vec<u8>,
+    // 解析时存在ZIP64结束记录；打包时为true则强制写出ZIP64结构
+    pub zip64: bool,
+    // 没有单独设置密码的条目使用该密码解压和加密打包
+    pub password: Option<Vec<u8>>,
+    // 解压时为加密条目(AES或传统加密)提供密码，优先于password
+    pub password_provider: Option<PasswordProvider>,
+    pub encryption: Encryption,
+    // 自定义扩展的解析函数，注册后已解析和新加入的条目都会转换
+    pub extra_registry: ExtraRegistry,
+    // 新增条目时DOS时间的换算方式
+    pub time_zone: TimeZone,
+    // 打包时固定条目顺序、时间和属性，相同输入得到相同输出
+    // 设置password时新加密条目的盐值和加密头部是随机的，每次输出都不同，只有原样写出的已加密条目不变
+    pub reproducible: bool,
+    // 解压时名称含..、绝对路径、盘符或NUL的处理方式
+    pub path_policy: PathPolicy,
+    // 解析和解压的上限，解析时取自ParseOptions
+    pub limits: Limits,
+    // 解压时校验每个条目的CRC-32和长度，默认开启
+    pub verify_crc32: bool,
+    // 只读中央目录解析时保存的压缩包和解析选项，条目在第一次使用时从中读取
+    pub lazy_source: Option<(T, ParseOptions)>,
     // #[br(seek_before = if model == ZipModel::Parse {
     //         SeekFrom::Start(offset as u64)
     //     } else {
@@ -202,6 +261,8 @@ where
     //     },args(&model,&config, entries,)
     // )]
     // #[bw(if(model == ZipModel::Bin),args(&model,))]
+    // 只读中央目录解析时，条目需先经entry()或load_all()读取本地头和数据，
+    // 直接从该字段取出的未读取条目解压时报"directory data is none"
     pub directories: IndexDirectory<T>,
 }
 impl<T> BinWrite for FastZip<T>
@@ -241,9 +302,15 @@ where
             writer.write_le(&self.number_of_disk).await?;
             writer.write_le(&self.directory_starts).await?;
             writer.write_le(&self.number_of_directory_disk).await?;
-            writer.write_le(&(self.directories.len() as u16)).await?;
-            writer.write_le(&self.size).await?;
-            writer.write_le(&self.offset).await?;
+            if *model == ZipModel::Bin {
+                writer.write_le(&(self.directories.len() as u64)).await?;
+                writer.write_le(&self.size).await?;
+                writer.write_le(&self.offset).await?;
+            } else {
+                writer.write_le(&(self.directories.len() as u16)).await?;
+                writer.write_le(&(self.size as u32)).await?;
+                writer.write_le(&(self.offset as u32)).await?;
+            }
             writer.write_le(&(self.comment.len() as u16)).await?;
             writer.write_le(&self.comment).await?;
             if *model == ZipModel::Bin {
@@ -260,7 +327,12 @@ where
     T::Config: Config,
 {
     type Args<'a>
-        = (&'a ZipModel, &'a T::Config, &'a mut ReadBytesCallback<'a>)
+        = (
+        &'a ZipModel,
+        &'a T::Config,
+        &'a ParseOptions,
+        &'a mut ReadBytesCallback<'a>,
+    )
     where
         T: 'a;
 
@@ -275,14 +347,19 @@ where
         Self: 'a,
     {
         async move {
-            let (model, config, read_bytes) = args;
+            let (model, config, options, read_bytes) = args;
             let pos = reader.position().await?;
             let magic: u32 = reader.read_le().await?;
-            assert_eq!(magic, 0x04034b50_u32);
+            if magic != 0x04034b50_u32 {
+                return Err(Error::BadMagic(
+                    pos,
+                    format!("magic {} not match for local file header", magic),
+                ));
+            }
             let crc32_computer = if *model == ZipModel::Bin {
                 reader.read_le::<bool>().await?
             } else {
-                false
+                true
             };
             let eocd_offset = if *model == ZipModel::Bin {
                 reader.read_le::<u64>().await?
@@ -291,24 +368,52 @@ where
                 reader.seek(SeekFrom::End(-(eocd_offset as i64))).await?;
                 eocd_offset
             };
+            let eocd_position = reader.position().await?;
             let magic: Magic = reader.read_le().await?;
             let number_of_disk: u16 = reader.read_le().await?;
             let directory_starts: u16 = reader.read_le().await?;
             let number_of_directory_disk: u16 = reader.read_le().await?;
             // #[bw(calc = directories.len() as u16)]
-            let entries: u16 = reader.read_le().await?;
-            let size: u32 = reader.read_le().await?;
-            let offset: u32 = reader.read_le().await?;
+            let (mut entries, mut size, mut offset) = if *model == ZipModel::Bin {
+                (
+                    reader.read_le::<u64>().await?,
+                    reader.read_le::<u64>().await?,
+                    reader.read_le::<u64>().await?,
+                )
+            } else {
+                (
+                    reader.read_le::<u16>().await? as u64,
+                    reader.read_le::<u32>().await? as u64,
+                    reader.read_le::<u32>().await? as u64,
+                )
+            };
             // #[bw(calc = comment.len() as u16)]
             let comment_length: u16 = reader.read_le().await?;
             // #[br(count = comment_length)]
             let comment: Vec<u8> = reader.read_le_args((comment_length as u64, ())).await?;
+            let mut zip64 = false;
             if *model == ZipModel::Parse {
-                reader.set_position(offset as u64).await?; // .seek(SeekFrom::Start(offset as u64)).await?;
+                if let Some(eocd) = parse_zip64_eocd(reader, endian, eocd_position).await? {
+                    entries = eocd.entries;
+                    size = eocd.size;
+                    offset = eocd.offset;
+                    zip64 = true;
+                }
+                reader.set_position(offset).await?; // .seek(SeekFrom::Start(offset as u64)).await?;
             }
-            read_bytes(reader.position().await? - pos).await?;
+            // 按声明的条目数分配前先检查
+            if let Some(limit) = options.limits.max_entries
+                && entries > limit
+            {
+                return Err(LimitError::TooManyEntries {
+                    count: entries,
+                    limit,
+                }
+                .into_error());
+            }
+            read_bytes(reader.position().await?.saturating_sub(pos)).await?;
             let directories: IndexDirectory<T> = reader
-                .read_le_args((model, config, entries, read_bytes))
+                .read_le_args((model, config, options, entries, read_bytes))
                 .await?;
             Ok(Self {
                 config: config.clone(),
@@ -323,6 +428,17 @@ where
                 offset,
                 comment_length,
                 comment,
+                zip64,
+                password: None,
+                password_provider: None,
+                encryption: Encryption::default(),
+                extra_registry: options.extra_registry.clone(),
+                time_zone: TimeZone::default(),
+                reproducible: false,
+                path_policy: PathPolicy::default(),
+                limits: options.limits.clone(),
+                verify_crc32: true,
+                lazy_source: None,
                 directories,
             })
         }
@@ -362,7 +478,8 @@ where
         = (
         &'a ZipModel,
         &'a T::Config,
-        u16,
+        &'a ParseOptions,
+        u64,
         &'a mut ReadBytesCallback<'a>,
     )
     where
@@ -379,13 +496,17 @@ where
         Self: 'a,
     {
         async move {
-            let (model, config, count, read_bytes) = args;
+            let (model, config, options, count, read_bytes) = args;
             let mut seen = HashSet::new();
-            let mut directories = IndexMap::with_capacity(count as usize);
+            //条目数来自文件，不可信，预分配设上限
+            let mut directories = IndexMap::with_capacity(count.min(u16::MAX as u64) as usize);
             for index in 0..count {
-                let dir: Directory<T> =
-                    Directory::read_options(reader, endian, (index, model, config, read_bytes))
-                        .await?;
+                let dir: Directory<T> = Directory::read_options(
+                    reader,
+                    endian,
+                    (index, model, config, options, read_bytes),
+                )
+                .await?;
                 let name = String::from_utf8(dir.file_name.inner.clone())
                     .map_err(|e| Error::Err(Box::new(e)))?;
                 let lower = name.to_lowercase();
@@ -421,7 +542,7 @@ where
         async move {
             let (model,) = args;
             for (_, v) in &self.0 {
-                v.write_options(writer, endian, (model,)).await?;
+                v.write_options(writer, endian, (model, false)).await?;
             }
             Ok(())
         }
@@ -435,9 +556,97 @@ where
     pub fn enable_crc32_computer(&mut self) {
         self.crc32_computer = true.into();
     }
+    /// 新条目的CRC-32记为0，本库解压这些条目前需disable_verify_crc32，其他工具会视为损坏
     pub fn disable_crc32_computer(&mut self) {
         self.crc32_computer = false.into();
     }
+    pub fn enable_verify_crc32(&mut self) {
+        self.verify_crc32 = true;
+    }
+    /// 跳过解压时的CRC-32和长度校验，损坏的条目不会报错
+    pub fn disable_verify_crc32(&mut self) {
+        self.verify_crc32 = false;
+    }
+    pub fn enable_zip64(&mut self) {
+        self.zip64 = true;
+    }
+    pub fn disable_zip64(&mut self) {
+        self.zip64 = false;
+    }
+    pub fn set_password(&mut self, password: impl Into<Vec<u8>>) {
+        self.password = Some(password.into());
+    }
+    pub fn set_password_provider(
+        &mut self,
+        provider: impl Fn(&str) -> Option<Vec<u8>> + Send + Sync + 'static,
+    ) {
+        self.password_provider = Some(Arc::new(provider));
+    }
+    /// 加密条目的盐值和加密头部是随机的，开启后输出仍不固定
+    pub fn set_reproducible(&mut self, reproducible: bool) {
+        self.reproducible = reproducible;
+    }
+    pub fn set_path_policy(&mut self, path_policy: PathPolicy) {
+        self.path_policy = path_policy;
+    }
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+    pub fn set_time_zone(&mut self, time_zone: TimeZone) {
+        self.time_zone = time_zone;
+    }
+    pub fn set_encryption(&mut self, encryption: Encryption) {
+        self.encryption = encryption;
+    }
+    /// 注册自定义扩展，已解析条目中对应ID的原始扩展立即转换为该类型
+    pub fn register_extra<E: ExtraField>(&mut self) {
+        self.extra_registry.register::<E>();
+        for (_, dir) in &mut self.directories.0 {
+            dir.resolve_extras(&self.extra_registry);
+        }
+    }
+    /// 解压时条目使用的密码，每次使用时按当前设置解析，不写回条目。
+    /// 条目单独设置的优先，其次password_provider(仅加密条目)，最后password
+    pub(crate) fn entry_password(&self, name: &str, dir: &Directory<T>) -> Option<Vec<u8>> {
+        if dir.password.is_some() {
+            return dir.password.clone();
+        }
+        if !dir.is_encrypted() {
+            return None;
+        }
+        match &self.password_provider {
+            Some(provider) => provider(name),
+            None => None,
+        }
+        .or_else(|| self.password.clone())
+    }
+    /// 按directories的顺序解析所有条目的密码
+    pub(crate) fn entry_passwords(&self) -> Vec<Option<Vec<u8>>> {
+        self.directories
+            .0
+            .iter()
+            .map(|(name, dir)| self.entry_password(name, dir))
+            .collect()
+    }
+    /// 解压前检查条目数，让所有条目共享同一个输出字节计数并同步校验开关
+    pub(crate) fn check_entries(&self) -> BinResult<()> {
+        let count = self.directories.len() as u64;
+        if let Some(limit) = self.limits.max_entries
+            && count > limit
+        {
+            return Err(LimitError::TooManyEntries { count, limit }.into_error());
+        }
+        Ok(())
+    }
+    pub(crate) fn apply_extract_options(&mut self) -> BinResult<()> {
+        self.check_entries()?;
+        let limits = ExtractLimits::new(self.limits.clone());
+        for (_, dir) in &mut self.directories.0 {
+            dir.limits = Some(limits.clone());
+            dir.verify_crc32 = self.verify_crc32;
+        }
+        Ok(())
+    }
 }
 impl<T> FastZip<T>
 where
@@ -447,7 +656,7 @@ where
     pub fn empty() -> FastZip<T> {
         Self {
             config: Default::default(),
-            crc32_computer: Default::default(),
+            crc32_computer: true,
             eocd_offset: 0,
             magic: Default::default(),
             number_of_disk: 0,
@@ -458,27 +667,59 @@ where
             offset: 0,
             comment_length: 0,
             comment: vec![],
+            zip64: false,
+            password: None,
+            password_provider: None,
+            encryption: Encryption::default(),
+            extra_registry: ExtraRegistry::default(),
+            time_zone: TimeZone::default(),
+            reproducible: false,
+            path_policy: PathPolicy::default(),
+            limits: Limits::default(),
+            verify_crc32: true,
+            lazy_source: None,
             directories: IndexDirectory(IndexMap::new()),
         }
     }
     pub fn parse(reader: &mut T) -> impl Future<Output = BinResult<FastZip<T>>> + Send {
+        async move {
+            let options = ParseOptions::default();
+            Self::parse_with_options(reader, &options).await
+        }
+    }
+    pub fn parse_with_options(
+        reader: &mut T,
+        options: &ParseOptions,
+    ) -> impl Future<Output = BinResult<FastZip<T>>> + Send {
         async move {
             let config = reader.config().clone();
+            let source = Self::lazy_source(reader, options).await?;
             let mut reader = BufReader::with_capacity(32 * 1024, reader);
-            let zip = FastZip::read_le_args(
+            let mut zip = FastZip::read_le_args(
                 &mut reader,
-                (&ZipModel::Parse, &config, &mut |_bytes| {
+                (&ZipModel::Parse, &config, options, &mut |_bytes| {
                     Box::pin(async { Ok(()) })
                 }),
             )
             .await?;
             reader.rewind_position().await?;
+            zip.lazy_source = source;
             Ok(zip)
         }
     }
     pub fn parse_with_callback(
         reader: &mut T,
         callback: impl FnMut(u64, u64) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> + Send,
+    ) -> impl Future<Output = BinResult<FastZip<T>>> + Send {
+        async move {
+            let options = ParseOptions::default();
+            Self::parse_with_options_callback(reader, &options, callback).await
+        }
+    }
+    pub fn parse_with_options_callback(
+        reader: &mut T,
+        options: &ParseOptions,
+        callback: impl FnMut(u64, u64) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> + Send,
     ) -> impl Future<Output = BinResult<FastZip<T>>> + Send {
         async move {
             let config = reader.config().clone();
@@ -487,14 +728,94 @@ where
             reader.set_position(pos).await?;
             let mut sum = 0;
             let mut buffered = 0;
+            let source = Self::lazy_source(reader, options).await?;
             let mut callback = Self::create_adapter(total, &mut buffered, &mut sum, callback);
             let mut reader = BufReader::with_capacity(32 * 1024, reader);
-            let result =
-                FastZip::read_le_args(&mut reader, (&ZipModel::Parse, &config, &mut callback))
-                    .await;
+            let result = FastZip::read_le_args(
+                &mut reader,
+                (&ZipModel::Parse, &config, options, &mut callback),
+            )
+            .await;
             reader.rewind_position().await?;
             callback(0).await?;
-            result
+            let mut zip = result?;
+            zip.lazy_source = source;
+            Ok(zip)
+        }
+    }
+    /// 只读中央目录并接管reader，条目在第一次使用时从中读取。
+    /// parse_with_options保存的是reader的link，Cursor<Vec<u8>>的link会复制整个缓冲区，内存中的压缩包应使用该方法
+    pub fn parse_lazy(
+        mut reader: T,
+        options: &ParseOptions,
+    ) -> impl Future<Output = BinResult<FastZip<T>>> + Send {
+        async move {
+            let mut options = options.clone();
+            options.lazy = true;
+            let config = reader.config().clone();
+            let mut buffered = BufReader::with_capacity(32 * 1024, &mut reader);
+            let mut zip = FastZip::read_le_args(
+                &mut buffered,
+                (&ZipModel::Parse, &config, &options, &mut |_bytes| {
+                    Box::pin(async { Ok(()) })
+                }),
+            )
+            .await?;
+            buffered.rewind_position().await?;
+            zip.lazy_source = Some((reader, options));
+            Ok(zip)
+        }
+    }
+    fn lazy_source(
+        reader: &T,
+        options: &ParseOptions,
+    ) -> impl Future<Output = BinResult<Option<(T, ParseOptions)>>> + Send {
+        async move {
+            if !options.lazy {
+                return Ok(None);
+            }
+            Ok(Some((reader.link().await?, options.clone())))
+        }
+    }
+    /// 返回名称对应的条目，只读中央目录解析时先读取其本地头和数据
+    pub fn entry(
+        &mut self,
+        name: &str,
+    ) -> impl Future<Output = BinResult<Option<&mut Directory<T>>>> + Send {
+        async move {
+            let Some(dir) = self.directories.0.get_mut(name) else {
+                return Ok(None);
+            };
+            if let Some((source, options)) = &mut self.lazy_source {
+                dir.load(source, options).await?;
+            }
+            Ok(Some(dir))
+        }
+    }
+    /// 读取所有尚未读取的条目
+    pub fn load_all(&mut self) -> impl Future<Output = BinResult<()>> + Send {
+        self.load_files(None)
+    }
+    /// files为None时读取所有条目
+    pub(crate) fn load_files<'a>(
+        &'a mut self,
+        files: Option<&'a [String]>,
+    ) -> impl Future<Output = BinResult<()>> + Send + 'a {
+        async move {
+            let Some((source, options)) = &mut self.lazy_source else {
+                return Ok(());
+            };
+            let files: Option<HashSet<&str>> =
+                files.map(|files| files.iter().map(String::as_str).collect());
+            for (name, dir) in &mut self.directories.0 {
+                if files
+                    .as_ref()
+                    .is_none_or(|files| files.contains(name.as_str()))
+                {
+                    dir.load(source, options).await?;
+                }
+            }
+            Ok(())
         }
     }
     pub fn remove_file(&mut self, file_name: &str) {
@@ -517,7 +838,9 @@ where
     pub fn add_directory(&mut self, mut dir: Directory<T>) -> BinResult<()> {
         if dir.file_name.inner != dir.file.file_name.inner {
             dir.file.file_name = dir.file_name.clone();
+            dir.file.raw_file_name = dir.file_name.bytes().to_vec();
         }
+        dir.resolve_extras(&self.extra_registry);
         let name =
             String::from_utf8(dir.file_name.inner.clone()).map_err(|e| Error::Err(Box::new(e)))?;
         self.directories.insert(name, dir);
@@ -535,14 +858,22 @@ where
         let ratio = non_text_count as f32 / data.len() as f32;
         ratio > bin_threshold
     }
+    /// 修改时间为当前时间(UTC)
     pub fn create_dir(
+        data: T,
+        file_name: &str,
+    ) -> impl Future<Output = BinResult<Directory<T>>> + Send {
+        Self::create_dir_with_times(data, file_name, EntryTimes::now(), TimeZone::Utc)
+    }
+    pub fn create_dir_with_times(
         mut data: T,
         file_name: &str,
+        times: EntryTimes,
+        zone: TimeZone,
     ) -> impl Future<Output = BinResult<Directory<T>>> + Send {
         async move {
             data.seek_start().await?;
-            let length = data.length().await?;
-            let uncompressed_size = length as u32;
+            let uncompressed_size = data.length().await?;
             let crc_32_uncompressed_data = 0; //data.crc32_value();
 
             let mut buffer = vec![0u8; std::cmp::min(uncompressed_size as usize, 1024)];
@@ -550,9 +881,7 @@ where
             data.seek_start().await?;
             let internal_file_attributes = if Self::is_binary(&buffer) { 0 } else { 1 };
 
-            let file_name = Name {
-                inner: file_name.as_bytes().to_vec(),
-            };
+            let file_name = Name::from(file_name);
             let extra_fields: ExtraList = vec![].into();
             //     vec![
             //     Extra::UnixExtendedTimestamp {
@@ -576,15 +905,20 @@ where
                 extract_os: 0,          //MS-DOS
                 flags: 0,
                 compression_method: CompressionMethod::Deflate,
-                last_modification_time: 39620,
-                last_modification_date: 23170,
+                last_modification_time: DosDateTime::MIN.time,
+                last_modification_date: DosDateTime::MIN.date,
                 crc_32_uncompressed_data,
                 compressed_size: 0,
                 uncompressed_size,
                 // extra_field_length,
                 number_of_starts: 0,
                 internal_file_attributes,
-                // external_file_attributes: 2175008768,
+                //目录drwxr-xr-x，文件-rw-r--r--
+                external_file_attributes: if is_dir(file_name.bytes()) {
+                    0x41ED0010
+                } else {
+                    0x81A40000
+                },
                 offset_of_local_file_header: 0,
                 file_name: file_name.clone(),
                 extra_fields: extra_fields.clone(),
@@ -594,8 +928,8 @@ where
                     flags: 0,
                     extract_os: 0, //MS-DOS
                     compression_method: CompressionMethod::Deflate,
-                    last_modification_time: 39620,
-                    last_modification_date: 23170,
+                    last_modification_time: DosDateTime::MIN.time,
+                    last_modification_date: DosDateTime::MIN.date,
                     crc_32_uncompressed_data,
                     compressed_size: 0,
                     uncompressed_size,
@@ -603,12 +937,20 @@ where
                     file_name_length: 0,
                     extra_field_length: 0,
                     file_name: file_name.clone(),
+                    raw_file_name: file_name.bytes().to_vec(),
                     extra_fields,
                     data_descriptor: None,
                     data_position: 0,
                 },
                 sha_value: None,
+                password: None,
+                encryption: None,
+                zstd_level: None,
+                limits: None,
+                verify_crc32: true,
+                loaded: true,
             };
+            directory.set_times(times, zone);
             let dir = directory.is_dir();
             if dir {
                 directory.flags = 0;
@@ -623,9 +965,19 @@ where
         &mut self,
         data: T,
         file_name: &str,
+    ) -> impl Future<Output = BinResult<()>> + Send {
+        self.add_file_with_times(data, file_name, EntryTimes::now())
+    }
+    /// 按time_zone写入DOS时间和扩展时间戳。NTFS扩展必须包含三个时间，
+    /// 只在times的访问和创建时间都存在时写入
+    pub fn add_file_with_times(
+        &mut self,
+        data: T,
+        file_name: &str,
+        times: EntryTimes,
     ) -> impl Future<Output = BinResult<()>> + Send {
         async move {
-            let dir = Self::create_dir(data, file_name).await?;
+            let dir = Self::create_dir_with_times(data, file_name, times, self.time_zone).await?;
             let lower = file_name.to_lowercase();
             let mut seen = IndexMap::new();
             for (name, _) in &self.directories.0 {
@@ -671,7 +1023,7 @@ where
             let mut total_size = 0;
             for (_, director) in &mut self.directories.0 {
                 total_size += if !director.compressed
-                    && director.compression_method == CompressionMethod::Deflate
+                    && director.compression_method != CompressionMethod::Store
                 {
                     if let Some(data) = &mut director.data {
                         data.length().await?
@@ -691,14 +1043,45 @@ where
         writer: &mut R,
     ) -> impl Future<Output = BinResult<()>> + Send {
         async move {
+            let entries = self.directories.len() as u64;
+            let zip64 = self.zip64
+                || entries >= 0xFFFF
+                || self.size >= ZIP64_LIMIT
+                || self.offset >= ZIP64_LIMIT;
+            if zip64 {
+                let eocd_offset = self.offset + self.size;
+                writer.write_le(&0x06064b50_u32).await?;
+                writer.write_le(&44_u64).await?; //记录剩余长度
+                writer
+                    .write_le(&(0x0300_u16 | ZIP64_VERSION as u16))
+                    .await?;
+                writer.write_le(&(ZIP64_VERSION as u16)).await?;
+                writer.write_le(&0_u32).await?;
+                writer.write_le(&0_u32).await?;
+                writer.write_le(&entries).await?;
+                writer.write_le(&entries).await?;
+                writer.write_le(&self.size).await?;
+                writer.write_le(&self.offset).await?;
+                //locator
+                writer.write_le(&0x07064b50_u32).await?;
+                writer.write_le(&0_u32).await?;
+                writer.write_le(&eocd_offset).await?;
+                writer.write_le(&1_u32).await?;
+            }
             writer.write_le(&self.magic).await?;
             writer.write_le(&self.number_of_disk).await?;
             writer.write_le(&self.directory_starts).await?;
             writer.write_le(&self.number_of_directory_disk).await?;
-            writer.write_le(&(self.directories.len() as u16)).await?;
-            writer.write_le(&self.size).await?;
-            writer.write_le(&self.offset).await?;
-            writer.write_le(&(self.comment.len() as u16)).await?;
+            writer.write_le(&(entries.min(0xFFFF) as u16)).await?;
+            writer.write_le(&(self.size.min(ZIP64_LIMIT) as u32)).await?;
+            writer.write_le(&(self.offset.min(ZIP64_LIMIT) as u32)).await?;
+            let comment_length = u16::try_from(self.comment.len()).map_err(|_| {
+                Error::AssertFail(format!(
+                    "comment length {} exceeds 65535",
+                    self.comment.len()
+                ))
+            })?;
+            writer.write_le(&comment_length).await?;
             writer.write_all(&self.comment).await?;
             Ok(())
         }
@@ -748,7 +1131,7 @@ pub fn parse_eocd_offset<R: Read + Seek + Send>(
                     break;
                 }
             }
-            if search_size >= max_eocd_size {
+            if search_size >= max_eocd_size || search_size >= file_size {
                 break;
             }
             search_size = (search_size * 2).min(file_size);
@@ -757,6 +1140,223 @@ pub fn parse_eocd_offset<R: Read + Seek + Send>(
     }
 }
 
+pub struct Zip64Eocd {
+    pub entries: u64,
+    pub size: u64,
+    pub offset: u64,
+}
+// ZIP64结束记录定位器紧挨在EOCD之前，不存在时返回None
+pub fn parse_zip64_eocd<R: Read + Seek + Send>(
+    reader: &mut R,
+    endian: Endian,
+    eocd_position: u64,
+) -> impl Future<Output = BinResult<Option<Zip64Eocd>>> + Send {
+    async move {
+        if eocd_position < 20 {
+            return Ok(None);
+        }
+        reader.set_position(eocd_position - 20).await?;
+        let magic: u32 = reader.read_type(endian).await?;
+        if magic != 0x07064b50_u32 {
+            return Ok(None);
+        }
+        let _disk_with_eocd: u32 = reader.read_type(endian).await?;
+        let eocd_offset: u64 = reader.read_type(endian).await?;
+        let _total_disks: u32 = reader.read_type(endian).await?;
+        reader.set_position(eocd_offset).await?;
+        let magic: u32 = reader.read_type(endian).await?;
+        if magic != 0x06064b50_u32 {
+            return Err(Error::BadMagic(
+                eocd_offset,
+                format!("magic {} not match for zip64 eocd", magic),
+            ));
+        }
+        let _record_size: u64 = reader.read_type(endian).await?;
+        let _created_zip_spec: u16 = reader.read_type(endian).await?;
+        let _extract_zip_spec: u16 = reader.read_type(endian).await?;
+        let _number_of_disk: u32 = reader.read_type(endian).await?;
+        let _directory_starts: u32 = reader.read_type(endian).await?;
+        let _number_of_directory_disk: u64 = reader.read_type(endian).await?;
+        let entries: u64 = reader.read_type(endian).await?;
+        let size: u64 = reader.read_type(endian).await?;
+        let offset: u64 = reader.read_type(endian).await?;
+        Ok(Some(Zip64Eocd {
+            entries,
+            size,
+            offset,
+        }))
+    }
+}
+
 pub fn is_dir(file_name: &[u8]) -> bool {
     matches!(file_name.last(), Some(b'/') | Some(b'\\'))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ZipError;
+    use miniz_oxide::deflate::CompressionLevel;
+    use std::io::Cursor;
+
+    async fn packaged() -> Vec<u8> {
+        let mut zip = FastZip::<Cursor<Vec<u8>>>::empty();
+        zip.add_file(Cursor::new(b"malformed".to_vec()), "a.txt")
+            .await
+            .unwrap();
+        let mut packaged = Cursor::new(vec![]);
+        zip.package(&mut packaged, CompressionLevel::DefaultLevel)
+            .await
+            .unwrap();
+        packaged.into_inner()
+    }
+
+    //没有注释时EOCD固定在末尾22字节，中央目录偏移在其第16字节
+    fn central_offset(data: &[u8]) -> usize {
+        let eocd = data.len() - 22;
+        u32::from_le_bytes(data[eocd + 16..eocd + 20].try_into().unwrap()) as usize
+    }
+
+    async fn parse(data: Vec<u8>) -> BinResult<FastZip<Cursor<Vec<u8>>>> {
+        FastZip::parse(&mut Cursor::new(data)).await
+    }
+
+    #[tokio::test]
+    async fn truncated_eocd() {
+        let mut data = packaged().await;
+        assert!(parse(data.clone()).await.is_ok());
+        data.truncate(data.len() - 4);
+        assert!(parse(data).await.is_err());
+        assert!(matches!(
+            parse(b"PK\x05\x06".to_vec()).await,
+            Err(Error::BadMagic(..))
+        ));
+    }
+
+    #[tokio::test]
+    async fn bad_central_magic() {
+        let mut data = packaged().await;
+        let offset = central_offset(&data);
+        data[offset] = 0;
+        assert!(matches!(parse(data).await, Err(Error::BadMagic(pos, _)) if pos == offset as u64));
+    }
+
+    #[tokio::test]
+    async fn extra_list_past_eof() {
+        //扩展区声明16字节，实际只有4字节
+        let mut reader = Cursor::new(vec![0x01, 0x00, 0x08, 0x00]);
+        assert!(ExtraList::read_le_args(&mut reader, 16u16).await.is_err());
+        //扩展头声明的长度超出扩展区，保留为原始字节
+        let mut reader = Cursor::new(vec![0x55, 0x54, 0x20, 0x00, 0x01]);
+        let list = ExtraList::read_le_args(&mut reader, 5u16).await.unwrap();
+        assert!(list.0.is_empty());
+        assert_eq!(list.1, vec![0x55, 0x54, 0x20, 0x00, 0x01]);
+    }
+
+    #[tokio::test]
+    async fn local_offset_past_file() {
+        let mut data = packaged().await;
+        //中央目录头第42字节为本地头偏移
+        let field = central_offset(&data) + 42;
+        let past = (data.len() as u32 + 100).to_le_bytes();
+        data[field..field + 4].copy_from_slice(&past);
+        assert!(parse(data).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn local_errors_carry_entry_name() {
+        //本地头偏移指向中央目录，签名不匹配
+        let mut data = packaged().await;
+        let offset = central_offset(&data);
+        let field = offset + 42;
+        data[field..field + 4].copy_from_slice(&(offset as u32).to_le_bytes());
+        let error = ZipError::from(parse(data).await.err().unwrap());
+        assert!(matches!(&error, ZipError::BadMagic { offset: pos, .. } if *pos == offset as u64));
+        assert_eq!(error.name(), Some("a.txt"));
+
+        //本地头只剩2字节，读取签名时到达末尾
+        let mut data = packaged().await;
+        let near_end = (data.len() as u32 - 2).to_le_bytes();
+        data[field..field + 4].copy_from_slice(&near_end);
+        let error = ZipError::from(parse(data).await.err().unwrap());
+        assert!(matches!(&error, ZipError::Io { .. }));
+        assert_eq!(error.name(), Some("a.txt"));
+    }
+
+    // 返回EOCD中的条目数和ZIP64结束记录，没有定位器时为None
+    async fn zip64_eocd(data: Vec<u8>) -> (u16, Option<Zip64Eocd>) {
+        let eocd = data.len() - 22;
+        let entries = u16::from_le_bytes(data[eocd + 10..eocd + 12].try_into().unwrap());
+        let mut reader = Cursor::new(data);
+        let zip64 = parse_zip64_eocd(&mut reader, Endian::Little, eocd as u64)
+            .await
+            .unwrap();
+        (entries, zip64)
+    }
+
+    #[tokio::test]
+    async fn zip64_for_many_entries() {
+        const COUNT: usize = 0x10000;
+        let mut zip = FastZip::<Cursor<Vec<u8>>>::empty();
+        //add_file每次都按名称查重，直接插入
+        for index in 0..COUNT {
+            let name = format!("{}.txt", index);
+            let dir = FastZip::create_dir_with_times(
+                Cursor::new(vec![]),
+                &name,
+                EntryTimes::modified(std::time::UNIX_EPOCH),
+                TimeZone::Utc,
+            )
+            .await
+            .unwrap();
+            zip.directories.insert(name, dir);
+        }
+        let mut packaged = Cursor::new(vec![]);
+        zip.package(&mut packaged, CompressionLevel::DefaultLevel)
+            .await
+            .unwrap();
+        let data = packaged.into_inner();
+        let offset = central_offset(&data);
+
+        let (entries, zip64) = zip64_eocd(data.clone()).await;
+        assert_eq!(entries, 0xFFFF);
+        let zip64 = zip64.unwrap();
+        assert_eq!(zip64.entries, COUNT as u64);
+        assert_eq!(zip64.offset, offset as u64);
+        //中央目录之后依次是ZIP64结束记录(56字节)、定位器(20字节)和EOCD
+        assert_eq!(zip64.offset + zip64.size, data.len() as u64 - 22 - 20 - 56);
+
+        let zip = parse(data).await.unwrap();
+        assert!(zip.zip64);
+        assert_eq!(zip.entries, COUNT as u64);
+        assert_eq!(zip.directories.len(), COUNT);
+        assert!(zip.directories.contains_key("65535.txt"));
+    }
+
+    #[tokio::test]
+    async fn zip64_opt_in() {
+        let (entries, zip64) = zip64_eocd(packaged().await).await;
+        assert_eq!(entries, 1);
+        assert!(zip64.is_none());
+
+        let mut zip = FastZip::<Cursor<Vec<u8>>>::empty();
+        zip.enable_zip64();
+        zip.add_file(Cursor::new(b"malformed".to_vec()), "a.txt")
+            .await
+            .unwrap();
+        let mut packaged = Cursor::new(vec![]);
+        zip.package(&mut packaged, CompressionLevel::DefaultLevel)
+            .await
+            .unwrap();
+        let data = packaged.into_inner();
+        let (entries, zip64) = zip64_eocd(data.clone()).await;
+        assert_eq!(entries, 1);
+        let zip64 = zip64.unwrap();
+        assert_eq!(zip64.entries, 1);
+        assert_eq!(zip64.offset, central_offset(&data) as u64);
+
+        let zip = parse(data).await.unwrap();
+        assert!(zip.zip64);
+        assert_eq!(zip.directories.len(), 1);
+    }
+}